@@ -14,12 +14,14 @@
 #![warn(clippy::all)]
 
 use crate::{
-  ClientId, Deposit, DepositHeld, DepositReversed, TxErr, TxId, TxResult, Withdraw,
+  Authorization, Capture, ClientId, Deposit, DepositHeld, DepositReversed, DisputePolicy,
+  JournalEntry, Release, Rounding, TxErrKind, TxId, TxState, Unlock, Void, Withdraw,
+  WithdrawHeld, WithdrawReversed,
 };
 use derive_more::Display;
 use rust_decimal::Decimal;
-use serde::ser::{Serialize, SerializeStruct, Serializer};
-use std::{collections::HashMap, marker::PhantomData};
+use serde::ser::{Error, Serialize, SerializeStruct, Serializer};
+use std::{collections::HashMap, hash::Hash, marker::PhantomData};
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub struct AccountLocked;
@@ -32,16 +34,27 @@ impl AccountState for AccountLocked {}
 impl AccountState for AccountUnlocked {}
 
 /// A client's account.
-#[derive(Debug, Display, PartialEq, Eq)]
+#[derive(Debug, Display, PartialEq, Eq, Clone)]
 #[display(fmt = "Account {} Available={}", id, available)]
 pub struct Account<State: AccountState = AccountUnlocked> {
   id: ClientId,
   available: Decimal,
   held: Decimal,
+  carried_held: Decimal,
+  debt: Decimal,
+  credit_limit: Decimal,
+  authorized: Decimal,
+  authorizations: HashMap<TxId, Authorization>,
+  authorizations_expired: HashMap<TxId, Authorization>,
   deposits: HashMap<TxId, Deposit>,
   deposits_held: HashMap<TxId, Deposit<DepositHeld>>,
   deposits_reversed: HashMap<TxId, Deposit<DepositReversed>>,
   withdraws: HashMap<TxId, Withdraw>,
+  withdraws_held: HashMap<TxId, Withdraw<WithdrawHeld>>,
+  withdraws_reversed: HashMap<TxId, Withdraw<WithdrawReversed>>,
+  unlocks: Vec<Unlock>,
+  releases: Vec<Release>,
+  journal: Option<Vec<JournalEntry>>,
   phantom: PhantomData<State>,
 }
 
@@ -59,7 +72,286 @@ impl<State: AccountState> Account<State> {
   }
 
   pub fn total(&self) -> Decimal {
-    self.available + self.held
+    self.available + self.held + self.authorized
+  }
+
+  /// Funds held by open [authorizations](Authorization), which are part of the total but
+  /// not of the held funds.
+  pub fn authorized(&self) -> Decimal {
+    self.authorized
+  }
+
+  /// Get the open authorization with the given id, if the account has one.
+  pub fn authorization(&self, tx: TxId) -> Option<&Authorization> {
+    self.authorizations.get(&tx)
+  }
+
+  /// Held funds carried over from an [opening balance](crate::OpeningBalance), which are
+  /// part of the held funds but do not belong to any known disputed transaction.
+  pub fn carried_held(&self) -> Decimal {
+    self.carried_held
+  }
+
+  /// The administrative unlocks of this account, oldest first.
+  pub fn unlocks(&self) -> &[Unlock] {
+    &self.unlocks
+  }
+
+  /// The administrative releases of carried-over held funds, oldest first.
+  pub fn releases(&self) -> &[Release] {
+    &self.releases
+  }
+
+  /// The account's journal, oldest entry first, if journaling is enabled.
+  pub fn journal(&self) -> Option<&[JournalEntry]> {
+    self.journal.as_deref()
+  }
+
+  /// Add an entry to the journal.
+  pub(crate) fn record(&mut self, entry: JournalEntry) {
+    self.journal.get_or_insert_with(Vec::default).push(entry);
+  }
+
+  /// Debit a fee from the available funds, which may make them negative.
+  pub(crate) fn charge(&mut self, fee: Decimal) {
+    self.available -= fee;
+  }
+
+  /// Credit a collected fee to the available funds. The database ensures that this cannot
+  /// overflow.
+  pub(crate) fn credit(&mut self, fee: Decimal) {
+    self.available += fee;
+  }
+
+  /// Disputed funds that could not be held because they were not available.
+  ///
+  /// See [DisputePolicy::HoldAvailable].
+  pub fn debt(&self) -> Decimal {
+    self.debt
+  }
+
+  /// How far withdrawals may take the available funds below zero.
+  pub fn credit_limit(&self) -> Decimal {
+    self.credit_limit
+  }
+
+  /// The credit in use, i.e. how far the available funds are below zero.
+  pub fn credit_used(&self) -> Decimal {
+    if self.available.is_sign_negative() {
+      -self.available
+    } else {
+      Decimal::ZERO
+    }
+  }
+
+  pub(crate) fn set_credit_limit(&mut self, limit: Decimal) {
+    self.credit_limit = limit;
+  }
+
+  /// Check that *amount* can be debited from the available funds without going below the
+  /// credit limit.
+  pub(crate) fn check_funds(&self, amount: Decimal) -> Result<(), TxErrKind> {
+    match self.available.checked_add(self.credit_limit) {
+      Some(funds) if amount > funds && self.credit_limit.is_zero() => {
+        Err(TxErrKind::Insufficient)
+      }
+      Some(funds) if amount > funds => Err(TxErrKind::CreditLimitExceeded),
+      _ => Ok(()),
+    }
+  }
+
+  /// Get the disputed amount of a deposit or withdrawal, if the account has a disputed
+  /// one with the given id.
+  pub fn disputed(&self, tx: TxId) -> Option<Decimal> {
+    match self.deposits_held.get(&tx) {
+      Some(deposit) => Some(deposit.disputed()),
+      None => self.withdraws_held.get(&tx).map(|withdraw| withdraw.disputed()),
+    }
+  }
+
+  /// Return carried-over held funds to the available funds.
+  pub(crate) fn release(&mut self, tx: Release) -> Result<(), TxErrKind> {
+    assert_eq!(self.id, tx.client());
+
+    let amount = tx.amount().unwrap_or(self.carried_held);
+
+    if amount.is_zero() || amount > self.carried_held {
+      return Err(TxErrKind::InvalidReleaseAmount);
+    }
+
+    assert!(self.carried_held <= self.held);
+
+    self.carried_held -= amount;
+    self.held -= amount;
+    self.available += amount;
+
+    // The database ensures that the transaction ID is not a duplicate.
+    self.releases.push(tx);
+
+    Ok(())
+  }
+
+  /// Release the authorizations that have expired by *now*, returning them sorted by id.
+  pub(crate) fn expire(&mut self, now: u64) -> Vec<Authorization> {
+    let mut expired: Vec<_> = self
+      .authorizations
+      .values()
+      .filter(|authorization| authorization.is_expired(now))
+      .copied()
+      .collect();
+    expired.sort_by_key(Authorization::id);
+
+    for authorization in &expired {
+      self.authorizations.remove(&authorization.id());
+      self.authorizations_expired.insert(authorization.id(), *authorization);
+      self.release_authorized(authorization.amount(), authorization.amount());
+    }
+
+    expired
+  }
+
+  /// The earliest expiry of an open authorization, if any expires.
+  pub(crate) fn next_expiry(&self) -> Option<u64> {
+    self.authorizations.values().filter_map(Authorization::expires).min()
+  }
+
+  /// Release *amount* of the authorized funds, of which *released* go back to the
+  /// available funds and the rest leaves the account.
+  fn release_authorized(&mut self, amount: Decimal, released: Decimal) {
+    assert!(amount <= self.authorized);
+
+    self.authorized -= amount;
+    self.available += released;
+  }
+
+  /// Get the state of a deposit or withdrawal, if the account has one with the given id.
+  pub fn tx_state(&self, tx: TxId) -> Option<TxState> {
+    if self.deposits.contains_key(&tx) || self.withdraws.contains_key(&tx) {
+      Some(TxState::Released)
+    } else if self.deposits_held.contains_key(&tx)
+      || self.withdraws_held.contains_key(&tx)
+    {
+      Some(TxState::Held)
+    } else if self.deposits_reversed.contains_key(&tx)
+      || self.withdraws_reversed.contains_key(&tx)
+    {
+      Some(TxState::Reversed)
+    } else {
+      None
+    }
+  }
+}
+
+/// The complete state of an account, as stored in a [database snapshot](crate::Snapshot).
+#[derive(serde::Serialize, serde::Deserialize, Debug)]
+pub(crate) struct AccountSnapshot {
+  id: ClientId,
+  locked: bool,
+  available: Decimal,
+  held: Decimal,
+  #[serde(default)]
+  carried_held: Decimal,
+  debt: Decimal,
+  #[serde(default)]
+  credit_limit: Decimal,
+  #[serde(default)]
+  authorized: Decimal,
+  #[serde(default)]
+  authorizations: Vec<Authorization>,
+  #[serde(default)]
+  authorizations_expired: Vec<Authorization>,
+  deposits: Vec<Deposit>,
+  deposits_held: Vec<Deposit<DepositHeld>>,
+  deposits_reversed: Vec<Deposit<DepositReversed>>,
+  withdraws: Vec<Withdraw>,
+  withdraws_held: Vec<Withdraw<WithdrawHeld>>,
+  withdraws_reversed: Vec<Withdraw<WithdrawReversed>>,
+  unlocks: Vec<Unlock>,
+  #[serde(default)]
+  releases: Vec<Release>,
+  journal: Option<Vec<JournalEntry>>,
+}
+
+impl AccountSnapshot {
+  pub(crate) fn id(&self) -> ClientId {
+    self.id
+  }
+}
+
+/// The values of *map*, sorted by key to make snapshots deterministic.
+fn sorted<K: Ord + Hash + Copy, V: Clone>(map: &HashMap<K, V>) -> Vec<V> {
+  let mut entries: Vec<_> = map.iter().collect();
+  entries.sort_by_key(|(key, _)| **key);
+  entries.into_iter().map(|(_, value)| value.clone()).collect()
+}
+
+impl<State: AccountState> Account<State> {
+  fn snapshot(&self, locked: bool) -> AccountSnapshot {
+    AccountSnapshot {
+      id: self.id,
+      locked,
+      available: self.available,
+      held: self.held,
+      carried_held: self.carried_held,
+      debt: self.debt,
+      credit_limit: self.credit_limit,
+      authorized: self.authorized,
+      authorizations: sorted(&self.authorizations),
+      authorizations_expired: sorted(&self.authorizations_expired),
+      deposits: sorted(&self.deposits),
+      deposits_held: sorted(&self.deposits_held),
+      deposits_reversed: sorted(&self.deposits_reversed),
+      withdraws: sorted(&self.withdraws),
+      withdraws_held: sorted(&self.withdraws_held),
+      withdraws_reversed: sorted(&self.withdraws_reversed),
+      unlocks: self.unlocks.clone(),
+      releases: self.releases.clone(),
+      journal: self.journal.clone(),
+    }
+  }
+
+  fn restore(snapshot: AccountSnapshot) -> Self {
+    Self {
+      id: snapshot.id,
+      available: snapshot.available,
+      held: snapshot.held,
+      carried_held: snapshot.carried_held,
+      debt: snapshot.debt,
+      credit_limit: snapshot.credit_limit,
+      authorized: snapshot.authorized,
+      authorizations: snapshot
+        .authorizations
+        .into_iter()
+        .map(|tx| (tx.id(), tx))
+        .collect(),
+      authorizations_expired: snapshot
+        .authorizations_expired
+        .into_iter()
+        .map(|tx| (tx.id(), tx))
+        .collect(),
+      deposits: snapshot.deposits.into_iter().map(|tx| (tx.id(), tx)).collect(),
+      deposits_held: snapshot.deposits_held.into_iter().map(|tx| (tx.id(), tx)).collect(),
+      deposits_reversed: snapshot
+        .deposits_reversed
+        .into_iter()
+        .map(|tx| (tx.id(), tx))
+        .collect(),
+      withdraws: snapshot.withdraws.into_iter().map(|tx| (tx.id(), tx)).collect(),
+      withdraws_held: snapshot
+        .withdraws_held
+        .into_iter()
+        .map(|tx| (tx.id(), tx))
+        .collect(),
+      withdraws_reversed: snapshot
+        .withdraws_reversed
+        .into_iter()
+        .map(|tx| (tx.id(), tx))
+        .collect(),
+      unlocks: snapshot.unlocks,
+      releases: snapshot.releases,
+      journal: snapshot.journal,
+      phantom: PhantomData,
+    }
   }
 }
 
@@ -69,33 +361,71 @@ impl Account<AccountUnlocked> {
       id,
       available: Decimal::ZERO,
       held: Decimal::ZERO,
+      carried_held: Decimal::ZERO,
+      debt: Decimal::ZERO,
+      credit_limit: Decimal::ZERO,
+      authorized: Decimal::ZERO,
+      authorizations: HashMap::default(),
+      authorizations_expired: HashMap::default(),
       deposits: HashMap::default(),
       deposits_held: HashMap::default(),
       deposits_reversed: HashMap::default(),
       withdraws: HashMap::default(),
+      withdraws_held: HashMap::default(),
+      withdraws_reversed: HashMap::default(),
+      unlocks: Vec::default(),
+      releases: Vec::default(),
+      journal: None,
       phantom: PhantomData,
     }
   }
 
+  /// Create an account from an opening balance of *available* funds, *held* funds and
+  /// *debt*.
+  pub(crate) fn open(
+    id: ClientId,
+    available: Decimal,
+    held: Decimal,
+    debt: Decimal,
+  ) -> Self {
+    let mut account = Account::new(id);
+    account.available = available;
+    account.held = held;
+    account.carried_held = held;
+    account.debt = debt;
+    account
+  }
+
   pub fn lock(self) -> Account<AccountLocked> {
     Account::<AccountLocked> {
       id: self.id,
       available: self.available,
       held: self.held,
+      carried_held: self.carried_held,
+      debt: self.debt,
+      credit_limit: self.credit_limit,
+      authorized: self.authorized,
+      authorizations: self.authorizations,
+      authorizations_expired: self.authorizations_expired,
       deposits: self.deposits,
       deposits_held: self.deposits_held,
       deposits_reversed: self.deposits_reversed,
       withdraws: self.withdraws,
+      withdraws_held: self.withdraws_held,
+      withdraws_reversed: self.withdraws_reversed,
+      unlocks: self.unlocks,
+      releases: self.releases,
+      journal: self.journal,
       phantom: PhantomData,
     }
   }
 
-  pub(crate) fn deposit(&mut self, tx: Deposit) -> TxResult {
+  pub(crate) fn deposit(&mut self, tx: Deposit) -> Result<(), TxErrKind> {
     assert_eq!(self.id, tx.client());
 
     if self.total().checked_add(tx.amount()).is_none() {
       // Depositing *amount* would overflow the total.
-      return Err(TxErr::Overflow);
+      return Err(TxErrKind::Overflow);
     }
 
     if let Some(sum) = self.available.checked_add(tx.amount()) {
@@ -106,15 +436,13 @@ impl Account<AccountUnlocked> {
     }
 
     // Depositing *amount* would overflow the available.
-    Err(TxErr::Overflow)
+    Err(TxErrKind::Overflow)
   }
 
-  pub(crate) fn withdraw(&mut self, tx: Withdraw) -> TxResult {
+  pub(crate) fn withdraw(&mut self, tx: Withdraw) -> Result<(), TxErrKind> {
     assert_eq!(self.id, tx.client());
 
-    if tx.amount() > self.available {
-      return Err(TxErr::Insufficient);
-    }
+    self.check_funds(tx.amount())?;
 
     // The database ensures that the transaction ID is not a duplicate.
     self.withdraws.insert(tx.id(), tx);
@@ -123,72 +451,497 @@ impl Account<AccountUnlocked> {
     Ok(())
   }
 
-  pub(crate) fn dispute(&mut self, tx: crate::Dispute) -> TxResult {
+  pub(crate) fn authorize(&mut self, tx: Authorization) -> Result<(), TxErrKind> {
     assert_eq!(self.id, tx.client());
 
+    self.check_funds(tx.amount())?;
+
+    // The database ensures that the transaction ID is not a duplicate.
+    self.authorizations.insert(tx.id(), tx);
+    self.available -= tx.amount();
+    self.authorized += tx.amount();
+
+    Ok(())
+  }
+
+  /// Check that *tx* can capture its authorization, returning the captured amount.
+  pub(crate) fn check_capture(&self, tx: &Capture) -> Result<Decimal, TxErrKind> {
+    if self.authorizations_expired.contains_key(&tx.id()) {
+      return Err(TxErrKind::AuthorizationExpired);
+    }
+
+    let authorization =
+      self.authorizations.get(&tx.id()).ok_or(TxErrKind::MissingTxForClient)?;
+
+    if let Some(timestamp) = tx.timestamp() {
+      if authorization.is_expired(timestamp) {
+        return Err(TxErrKind::AuthorizationExpired);
+      }
+    }
+
+    let captured = tx.amount().unwrap_or_else(|| authorization.amount());
+
+    if captured > authorization.amount() {
+      return Err(TxErrKind::ExcessiveCapture);
+    }
+
+    Ok(captured)
+  }
+
+  pub(crate) fn capture(&mut self, tx: Capture) -> Result<(), TxErrKind> {
     let id = tx.id();
+    let captured = self.check_capture(&tx)?;
+    let authorization = self.authorizations[&id];
+    let withdraw = Withdraw::new(id, self.id, captured)?;
 
-    let deposit = match self.deposits.remove(&id) {
-      Some(deposit) => deposit,
-      None => return Err(TxErr::MissingTxForClient),
-    };
+    // The captured funds leave the account and the rest is released.
+    self.authorizations.remove(&id);
+    self.release_authorized(authorization.amount(), authorization.amount() - captured);
 
-    assert!(!self.deposits_held.contains_key(&id));
-    assert!(!self.deposits_reversed.contains_key(&id));
+    // The capture stands as a withdrawal, so that it can be disputed.
+    self.withdraws.insert(id, withdraw);
+
+    Ok(())
+  }
 
-    if deposit.amount() > self.available() {
-      self.deposits.insert(id, deposit);
-      return Err(TxErr::Insufficient);
+  pub(crate) fn void(&mut self, tx: Void) -> Result<(), TxErrKind> {
+    if self.authorizations_expired.contains_key(&tx.id()) {
+      return Err(TxErrKind::AuthorizationExpired);
     }
 
-    self.available -= deposit.amount();
-    self.held += deposit.amount();
+    let authorization =
+      self.authorizations.remove(&tx.id()).ok_or(TxErrKind::MissingTxForClient)?;
 
-    self.deposits_held.insert(id, deposit.hold());
+    self.release_authorized(authorization.amount(), authorization.amount());
 
     Ok(())
   }
 
-  pub(crate) fn resolve(&mut self, tx: crate::Resolve) -> TxResult {
+  pub(crate) fn dispute(
+    &mut self,
+    tx: crate::Dispute,
+    policy: DisputePolicy,
+  ) -> Result<(), TxErrKind> {
+    assert_eq!(self.id, tx.client());
+
     let id = tx.id();
 
-    let deposit = match self.deposits_held.remove(&id) {
-      Some(deposit) => deposit,
-      None => return Err(TxErr::MissingTxForClient),
+    if let Some(deposit) = self.deposits.remove(&id) {
+      let amount = tx.amount().unwrap_or_else(|| deposit.amount());
+
+      match self.hold_deposit(amount, deposit.amount(), policy) {
+        Ok(debt) => self.deposits_held.insert(id, deposit.hold(amount, debt)),
+        Err(err) => {
+          self.deposits.insert(id, deposit);
+          return Err(err);
+        }
+      };
+
+      return Ok(());
+    }
+
+    if let Some(deposit) = self.deposits_held.get(&id) {
+      let amount = tx.amount().unwrap_or_else(|| deposit.undisputed());
+      let debt = self.hold_deposit(amount, deposit.undisputed(), policy)?;
+
+      if let Some(deposit) = self.deposits_held.get_mut(&id) {
+        deposit.hold_more(amount, debt);
+      }
+
+      return Ok(());
+    }
+
+    if let Some(withdraw) = self.withdraws.remove(&id) {
+      let amount = tx.amount().unwrap_or_else(|| withdraw.amount());
+
+      if let Err(err) = self.hold_withdraw(amount, withdraw.amount()) {
+        self.withdraws.insert(id, withdraw);
+        return Err(err);
+      }
+
+      self.withdraws_held.insert(id, withdraw.hold(amount));
+      return Ok(());
+    }
+
+    if let Some(withdraw) = self.withdraws_held.get(&id) {
+      let amount = tx.amount().unwrap_or_else(|| withdraw.undisputed());
+      self.hold_withdraw(amount, withdraw.undisputed())?;
+
+      if let Some(withdraw) = self.withdraws_held.get_mut(&id) {
+        withdraw.hold_more(amount);
+      }
+
+      return Ok(());
+    }
+
+    Err(TxErrKind::MissingTxForClient)
+  }
+
+  /// Hold *amount* out of a disputed deposit, where at most *undisputed* can be held.
+  ///
+  /// Returns the part of *amount* that could not be held and was recorded as debt, which
+  /// can only be non-zero with the [DisputePolicy::HoldAvailable] policy.
+  fn hold_deposit(
+    &mut self,
+    amount: Decimal,
+    undisputed: Decimal,
+    policy: DisputePolicy,
+  ) -> Result<Decimal, TxErrKind> {
+    if amount.is_zero() || amount > undisputed {
+      return Err(TxErrKind::InvalidDisputeAmount);
+    }
+
+    let debt = if amount > self.available() {
+      match policy {
+        DisputePolicy::Reject => return Err(TxErrKind::Insufficient),
+        DisputePolicy::AllowNegative => Decimal::ZERO,
+        DisputePolicy::HoldAvailable => amount - self.available().max(Decimal::ZERO),
+      }
+    } else {
+      Decimal::ZERO
     };
 
-    assert!(!self.deposits.contains_key(&id));
-    assert!(!self.deposits_reversed.contains_key(&id));
-    assert!(deposit.amount() <= self.held());
+    let held = amount - debt;
+
+    if self.debt.checked_add(debt).is_none() {
+      return Err(TxErrKind::Overflow);
+    }
 
-    self.available += deposit.amount();
-    self.held -= deposit.amount();
+    self.available -= held;
+    self.held += held;
+    self.debt += debt;
 
-    self.deposits.insert(id, deposit.release());
+    Ok(debt)
+  }
+
+  /// Hold *amount* out of a disputed withdrawal, where at most *undisputed* can be held.
+  fn hold_withdraw(
+    &mut self,
+    amount: Decimal,
+    undisputed: Decimal,
+  ) -> Result<(), TxErrKind> {
+    if amount.is_zero() || amount > undisputed {
+      return Err(TxErrKind::InvalidDisputeAmount);
+    }
+
+    // The withdrawn amount is put back into the account as held funds, which would
+    // increase the total.
+    if self.total().checked_add(amount).is_none() {
+      return Err(TxErrKind::Overflow);
+    }
+
+    self.held += amount;
 
     Ok(())
   }
 
-  pub(crate) fn chargeback(&mut self, tx: crate::Chargeback) -> TxResult {
-    let id = tx.id();
+  /// Check that *amount* of the withdrawal *id* can be credited back, without changing
+  /// the account.
+  pub(crate) fn check_refund(&self, id: TxId, amount: Decimal) -> Result<(), TxErrKind> {
+    let withdraw = self.withdraws.get(&id).ok_or(TxErrKind::MissingTxForClient)?;
+    assert!(amount <= withdraw.amount());
 
-    let deposit = match self.deposits_held.remove(&id) {
-      Some(deposit) => deposit,
-      None => return Err(TxErr::MissingTxForClient),
-    };
+    if self.total().checked_add(amount).is_none() {
+      return Err(TxErrKind::Overflow);
+    }
 
-    assert!(!self.deposits.contains_key(&id));
-    assert!(!self.deposits_held.contains_key(&id));
-    assert!(deposit.amount() <= self.held());
+    Ok(())
+  }
 
-    self.held -= deposit.amount();
+  /// Credit back *amount* of the withdrawal *id*, e.g. to the source of a transfer whose
+  /// deposit into the destination was charged back.
+  pub(crate) fn refund(&mut self, id: TxId, amount: Decimal) -> Result<(), TxErrKind> {
+    self.check_refund(id, amount)?;
 
-    self.deposits_reversed.insert(id, deposit.reverse());
+    let withdraw = self.withdraws.remove(&id).expect("Refund was checked");
+    self.available += amount;
+    self.withdraws_reversed.insert(id, withdraw.hold(amount).reverse());
 
     Ok(())
   }
+
+  pub(crate) fn resolve(&mut self, tx: crate::Resolve) -> Result<(), TxErrKind> {
+    let id = tx.id();
+
+    if let Some(deposit) = self.deposits_held.remove(&id) {
+      assert!(!self.deposits.contains_key(&id));
+      assert!(!self.deposits_reversed.contains_key(&id));
+      assert!(deposit.held() <= self.held());
+      assert!(deposit.debt() <= self.debt());
+
+      // The dispute was unfounded: the held funds are released and the debt is forgiven.
+      self.available += deposit.held();
+      self.held -= deposit.held();
+      self.debt -= deposit.debt();
+
+      self.deposits.insert(id, deposit.release());
+
+      return Ok(());
+    }
+
+    if let Some(withdraw) = self.withdraws_held.remove(&id) {
+      assert!(!self.withdraws.contains_key(&id));
+      assert!(!self.withdraws_reversed.contains_key(&id));
+      assert!(withdraw.disputed() <= self.held());
+
+      // The withdrawal stands: the held funds leave the account again.
+      self.held -= withdraw.disputed();
+
+      self.withdraws.insert(id, withdraw.release());
+
+      return Ok(());
+    }
+
+    Err(TxErrKind::MissingTxForClient)
+  }
+
+  pub(crate) fn chargeback(&mut self, tx: crate::Chargeback) -> Result<(), TxErrKind> {
+    let id = tx.id();
+
+    if let Some(deposit) = self.deposits_held.remove(&id) {
+      assert!(!self.deposits.contains_key(&id));
+      assert!(!self.deposits_reversed.contains_key(&id));
+      assert!(deposit.held() <= self.held());
+      assert!(deposit.debt() <= self.debt());
+
+      // Any debt that was recorded for the deposit remains with the account.
+      self.held -= deposit.held();
+
+      self.deposits_reversed.insert(id, deposit.reverse());
+
+      return Ok(());
+    }
+
+    if let Some(withdraw) = self.withdraws_held.remove(&id) {
+      assert!(!self.withdraws.contains_key(&id));
+      assert!(!self.withdraws_reversed.contains_key(&id));
+      assert!(withdraw.disputed() <= self.held());
+
+      // The withdrawal is reversed: the client is credited back.
+      self.held -= withdraw.disputed();
+      self.available += withdraw.disputed();
+
+      self.withdraws_reversed.insert(id, withdraw.reverse());
+
+      return Ok(());
+    }
+
+    Err(TxErrKind::MissingTxForClient)
+  }
+}
+
+/// An account in either of its states, as kept by the [database](crate::Db).
+#[derive(Debug, Display, PartialEq, Eq, Clone)]
+pub enum AnyAccount {
+  #[display(fmt = "{}", _0)]
+  Unlocked(Account<AccountUnlocked>),
+
+  #[display(fmt = "{} (Locked)", _0)]
+  Locked(Account<AccountLocked>),
+}
+
+impl AnyAccount {
+  pub fn id(&self) -> ClientId {
+    match self {
+      AnyAccount::Unlocked(account) => account.id(),
+      AnyAccount::Locked(account) => account.id(),
+    }
+  }
+
+  pub fn available(&self) -> Decimal {
+    match self {
+      AnyAccount::Unlocked(account) => account.available(),
+      AnyAccount::Locked(account) => account.available(),
+    }
+  }
+
+  pub fn held(&self) -> Decimal {
+    match self {
+      AnyAccount::Unlocked(account) => account.held(),
+      AnyAccount::Locked(account) => account.held(),
+    }
+  }
+
+  pub fn total(&self) -> Decimal {
+    match self {
+      AnyAccount::Unlocked(account) => account.total(),
+      AnyAccount::Locked(account) => account.total(),
+    }
+  }
+
+  pub fn debt(&self) -> Decimal {
+    match self {
+      AnyAccount::Unlocked(account) => account.debt(),
+      AnyAccount::Locked(account) => account.debt(),
+    }
+  }
+
+  pub fn authorized(&self) -> Decimal {
+    match self {
+      AnyAccount::Unlocked(account) => account.authorized(),
+      AnyAccount::Locked(account) => account.authorized(),
+    }
+  }
+
+  pub fn authorization(&self, tx: TxId) -> Option<&Authorization> {
+    match self {
+      AnyAccount::Unlocked(account) => account.authorization(tx),
+      AnyAccount::Locked(account) => account.authorization(tx),
+    }
+  }
+
+  /// The available, held and authorized funds, as recorded in the journal.
+  pub(crate) fn funds(&self) -> (Decimal, Decimal, Decimal) {
+    (self.available(), self.held(), self.authorized())
+  }
+
+  pub fn credit_limit(&self) -> Decimal {
+    match self {
+      AnyAccount::Unlocked(account) => account.credit_limit(),
+      AnyAccount::Locked(account) => account.credit_limit(),
+    }
+  }
+
+  pub fn credit_used(&self) -> Decimal {
+    match self {
+      AnyAccount::Unlocked(account) => account.credit_used(),
+      AnyAccount::Locked(account) => account.credit_used(),
+    }
+  }
+
+  pub fn journal(&self) -> Option<&[JournalEntry]> {
+    match self {
+      AnyAccount::Unlocked(account) => account.journal(),
+      AnyAccount::Locked(account) => account.journal(),
+    }
+  }
+
+  pub fn is_locked(&self) -> bool {
+    matches!(self, AnyAccount::Locked(_))
+  }
+
+  pub fn tx_state(&self, tx: TxId) -> Option<TxState> {
+    match self {
+      AnyAccount::Unlocked(account) => account.tx_state(tx),
+      AnyAccount::Locked(account) => account.tx_state(tx),
+    }
+  }
+
+  pub fn disputed(&self, tx: TxId) -> Option<Decimal> {
+    match self {
+      AnyAccount::Unlocked(account) => account.disputed(tx),
+      AnyAccount::Locked(account) => account.disputed(tx),
+    }
+  }
+
+  pub(crate) fn record(&mut self, entry: JournalEntry) {
+    match self {
+      AnyAccount::Unlocked(account) => account.record(entry),
+      AnyAccount::Locked(account) => account.record(entry),
+    }
+  }
+
+  pub(crate) fn set_credit_limit(&mut self, limit: Decimal) {
+    match self {
+      AnyAccount::Unlocked(account) => account.set_credit_limit(limit),
+      AnyAccount::Locked(account) => account.set_credit_limit(limit),
+    }
+  }
+
+  pub(crate) fn release(&mut self, tx: Release) -> Result<(), TxErrKind> {
+    match self {
+      AnyAccount::Unlocked(account) => account.release(tx),
+      AnyAccount::Locked(account) => account.release(tx),
+    }
+  }
+
+  pub(crate) fn expire(&mut self, now: u64) -> Vec<Authorization> {
+    match self {
+      AnyAccount::Unlocked(account) => account.expire(now),
+      AnyAccount::Locked(account) => account.expire(now),
+    }
+  }
+
+  pub(crate) fn next_expiry(&self) -> Option<u64> {
+    match self {
+      AnyAccount::Unlocked(account) => account.next_expiry(),
+      AnyAccount::Locked(account) => account.next_expiry(),
+    }
+  }
+
+  pub(crate) fn charge(&mut self, fee: Decimal) {
+    match self {
+      AnyAccount::Unlocked(account) => account.charge(fee),
+      AnyAccount::Locked(account) => account.charge(fee),
+    }
+  }
+
+  pub(crate) fn credit(&mut self, fee: Decimal) {
+    match self {
+      AnyAccount::Unlocked(account) => account.credit(fee),
+      AnyAccount::Locked(account) => account.credit(fee),
+    }
+  }
+
+  pub(crate) fn snapshot(&self) -> AccountSnapshot {
+    match self {
+      AnyAccount::Unlocked(account) => account.snapshot(false),
+      AnyAccount::Locked(account) => account.snapshot(true),
+    }
+  }
+
+  pub(crate) fn restore(snapshot: AccountSnapshot) -> Self {
+    if snapshot.locked {
+      AnyAccount::Locked(Account::restore(snapshot))
+    } else {
+      AnyAccount::Unlocked(Account::restore(snapshot))
+    }
+  }
+}
+
+impl From<Account<AccountUnlocked>> for AnyAccount {
+  fn from(account: Account<AccountUnlocked>) -> Self {
+    AnyAccount::Unlocked(account)
+  }
+}
+
+impl From<Account<AccountLocked>> for AnyAccount {
+  fn from(account: Account<AccountLocked>) -> Self {
+    AnyAccount::Locked(account)
+  }
 }
 
+impl Account<AccountLocked> {
+  pub fn unlock(mut self, tx: Unlock) -> Account<AccountUnlocked> {
+    assert_eq!(self.id, tx.client());
+
+    self.unlocks.push(tx);
+
+    Account::<AccountUnlocked> {
+      id: self.id,
+      available: self.available,
+      held: self.held,
+      carried_held: self.carried_held,
+      debt: self.debt,
+      credit_limit: self.credit_limit,
+      authorized: self.authorized,
+      authorizations: self.authorizations,
+      authorizations_expired: self.authorizations_expired,
+      deposits: self.deposits,
+      deposits_held: self.deposits_held,
+      deposits_reversed: self.deposits_reversed,
+      withdraws: self.withdraws,
+      withdraws_held: self.withdraws_held,
+      withdraws_reversed: self.withdraws_reversed,
+      unlocks: self.unlocks,
+      releases: self.releases,
+      journal: self.journal,
+      phantom: PhantomData,
+    }
+  }
+}
+
+#[allow(clippy::too_many_arguments)]
 fn serialize_account<S: Serializer>(
   serializer: S,
   id: ClientId,
@@ -196,13 +949,33 @@ fn serialize_account<S: Serializer>(
   held: Decimal,
   total: Decimal,
   locked: bool,
+  authorized: Decimal,
+  debt: Option<Decimal>,
+  credit_used: Option<Decimal>,
+  dispute_policy: Option<DisputePolicy>,
 ) -> Result<S::Ok, S::Error> {
-  let mut state = serializer.serialize_struct("Account", 5)?;
+  let mut state = serializer.serialize_struct("Account", 9)?;
   state.serialize_field("client", &id)?;
   state.serialize_field("available", &available)?;
   state.serialize_field("held", &held)?;
   state.serialize_field("total", &total)?;
   state.serialize_field("locked", &locked)?;
+  state.serialize_field("authorized", &authorized)?;
+
+  // The optional columns are left out entirely rather than left empty, so that they do
+  // not widen the output unless their feature is in use.
+  if let Some(debt) = debt {
+    state.serialize_field("debt", &debt)?;
+  }
+
+  if let Some(credit_used) = credit_used {
+    state.serialize_field("credit_used", &credit_used)?;
+  }
+
+  if let Some(dispute_policy) = dispute_policy {
+    state.serialize_field("dispute_policy", &dispute_policy)?;
+  }
+
   state.end()
 }
 
@@ -215,6 +988,10 @@ impl Serialize for Account<AccountUnlocked> {
       self.held(),
       self.total(),
       false,
+      self.authorized(),
+      Some(self.debt()),
+      Some(self.credit_used()),
+      None,
     )
   }
 }
@@ -228,13 +1005,200 @@ impl Serialize for Account<AccountLocked> {
       self.held(),
       self.total(),
       true,
+      self.authorized(),
+      Some(self.debt()),
+      Some(self.credit_used()),
+      None,
+    )
+  }
+}
+
+impl Serialize for AnyAccount {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    match self {
+      AnyAccount::Unlocked(account) => account.serialize(serializer),
+      AnyAccount::Locked(account) => account.serialize(serializer),
+    }
+  }
+}
+
+/// An account whose amounts are serialized with a fixed number of decimal places, see
+/// [AnyAccount::rounded].
+#[derive(Debug, Clone, Copy)]
+pub struct RoundedAccount<'a> {
+  account: &'a AnyAccount,
+  scale: u32,
+  rounding: Rounding,
+  debt: bool,
+  credit_used: bool,
+  dispute_policy: Option<DisputePolicy>,
+}
+
+impl AnyAccount {
+  /// Get a view of the account that serializes its amounts rounded to exactly *scale*
+  /// decimal places. The total is the sum of the rounded available, held and authorized
+  /// funds, so that every row adds up.
+  ///
+  /// Serializing fails if an amount has too many digits for the scale, see
+  /// [RoundedAccount::check].
+  pub fn rounded(&self, scale: u32, rounding: Rounding) -> RoundedAccount<'_> {
+    RoundedAccount {
+      account: self,
+      scale,
+      rounding,
+      debt: false,
+      credit_used: false,
+      dispute_policy: None,
+    }
+  }
+}
+
+impl RoundedAccount<'_> {
+  /// Also serialize the debt recorded for disputes, see [DisputePolicy::HoldAvailable].
+  pub fn with_debt(mut self) -> Self {
+    self.debt = true;
+    self
+  }
+
+  /// Also serialize the credit in use, see [AnyAccount::credit_used].
+  pub fn with_credit_used(mut self) -> Self {
+    self.credit_used = true;
+    self
+  }
+
+  /// Also serialize the dispute policy the account is subject to, which is a setting of
+  /// the [database](crate::Db) rather than of the account itself.
+  pub fn with_dispute_policy(mut self, policy: DisputePolicy) -> Self {
+    self.dispute_policy = Some(policy);
+    self
+  }
+
+  /// Check that every amount to serialize can be written with exactly the scale's decimal
+  /// places, or get the first one that has too many digits.
+  pub fn check(&self) -> Result<(), Decimal> {
+    self.amounts().map(|_| ())
+  }
+
+  /// Get the rounded available, held, authorized and total funds, and the debt and
+  /// credit used if they are serialized.
+  #[allow(clippy::type_complexity)]
+  fn amounts(
+    &self,
+  ) -> Result<
+    (Decimal, Decimal, Decimal, Decimal, Option<Decimal>, Option<Decimal>),
+    Decimal,
+  > {
+    let round = |value| self.rounding.round_exact(value, self.scale).ok_or(value);
+    let available = round(self.account.available())?;
+    let held = round(self.account.held())?;
+    let authorized = round(self.account.authorized())?;
+
+    // The sum of exact amounts can still lose decimal places.
+    let total = available + held + authorized;
+
+    if total.scale() != self.scale {
+      return Err(total);
+    }
+
+    let debt = self.debt.then(|| round(self.account.debt())).transpose()?;
+    let credit_used =
+      self.credit_used.then(|| round(self.account.credit_used())).transpose()?;
+    Ok((available, held, authorized, total, debt, credit_used))
+  }
+}
+
+impl Serialize for RoundedAccount<'_> {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let (available, held, authorized, total, debt, credit_used) =
+      self.amounts().map_err(|value| {
+        S::Error::custom(format!(
+          "{} does not fit in {} decimal places",
+          value, self.scale
+        ))
+      })?;
+
+    serialize_account(
+      serializer,
+      self.account.id(),
+      available,
+      held,
+      total,
+      self.account.is_locked(),
+      authorized,
+      debt,
+      credit_used,
+      self.dispute_policy,
     )
   }
 }
 
 #[cfg(test)]
 mod account_tests {
-  use crate::{Account, ClientId, Deposit, TxId, Withdraw};
+  use crate::{
+    Account, AnyAccount, Chargeback, ClientId, Deposit, Dispute, DisputePolicy, Resolve,
+    Rounding, TxErrKind, TxId, Withdraw,
+  };
+  use rust_decimal::Decimal;
+  use std::str::FromStr;
+
+  #[test]
+  fn serialize_rounded() {
+    let client = ClientId::new(1);
+    let mut account = Account::new(client);
+    let amount = Decimal::from_str("1.23456").unwrap();
+    let tx = Deposit::new(TxId::new(1), client, amount).unwrap();
+    assert_eq!(account.deposit(tx), Ok(()));
+
+    let account = AnyAccount::from(account);
+    let rounded = account.rounded(4, Rounding::HalfEven);
+    let value = serde_json::to_value(rounded).unwrap();
+    assert_eq!(value["available"], "1.2346");
+    assert!(value.get("debt").is_none());
+    assert!(value.get("credit_used").is_none());
+    assert!(value.get("dispute_policy").is_none());
+
+    let rounded = rounded.with_debt().with_credit_used();
+    let rounded = rounded.with_dispute_policy(DisputePolicy::HoldAvailable);
+    let value = serde_json::to_value(rounded).unwrap();
+    assert_eq!(value["debt"], "0.0000");
+    assert_eq!(value["credit_used"], "0.0000");
+    assert_eq!(value["dispute_policy"], "debt");
+
+    // Half of the funds are held, and both halves round down to even on their own. The
+    // total must still be their sum.
+    let mut account = Account::new(client);
+    let amount = Decimal::from_str("0.00005").unwrap();
+    let tx = Deposit::new(TxId::new(1), client, amount).unwrap();
+    assert_eq!(account.deposit(tx), Ok(()));
+    let tx = Deposit::new(TxId::new(2), client, amount).unwrap();
+    assert_eq!(account.deposit(tx), Ok(()));
+    let tx = Dispute::new(TxId::new(2), client, None).unwrap();
+    assert_eq!(account.dispute(tx, DisputePolicy::Reject), Ok(()));
+
+    let account = AnyAccount::from(account);
+    let value = serde_json::to_value(account.rounded(4, Rounding::HalfEven)).unwrap();
+    assert_eq!(value["available"], "0.0000");
+    assert_eq!(value["held"], "0.0000");
+    assert_eq!(value["total"], "0.0000");
+
+    // Every amount gets exactly the scale, or none is written at all.
+    let rounded = account.rounded(28, Rounding::HalfEven);
+    assert_eq!(rounded.check(), Ok(()));
+    assert_eq!(
+      serde_json::to_value(rounded).unwrap()["available"].as_str().unwrap().len(),
+      30
+    );
+
+    let mut account = Account::new(client);
+    let tx =
+      Deposit::new(TxId::new(1), client, Decimal::from_str("9.25").unwrap()).unwrap();
+    assert_eq!(account.deposit(tx), Ok(()));
+    let account = AnyAccount::from(account);
+    let rounded = account.rounded(28, Rounding::HalfEven);
+    assert_eq!(rounded.check(), Err(Decimal::from_str("9.25").unwrap()));
+    assert!(serde_json::to_value(rounded).is_err());
+    assert_eq!(account.rounded(27, Rounding::HalfEven).check(), Ok(()));
+  }
 
   #[test]
   fn deposits_withdraws() {
@@ -283,4 +1247,87 @@ mod account_tests {
     // The call to withdraw() should fail the client ID assertion.
     assert_eq!(account.withdraw(tx), Ok(()));
   }
+
+  #[test]
+  fn withdraw_dispute_resolve() {
+    let client = ClientId::new(1);
+    let mut account = Account::new(client);
+
+    let tx = Deposit::new(TxId::new(1), client, 5.into()).unwrap();
+    assert_eq!(account.deposit(tx), Ok(()));
+    let tx = Withdraw::new(TxId::new(2), client, 3.into()).unwrap();
+    assert_eq!(account.withdraw(tx), Ok(()));
+
+    let tx = Dispute::new(TxId::new(2), client, None).unwrap();
+    assert_eq!(account.dispute(tx, DisputePolicy::Reject), Ok(()));
+    assert_eq!(account.available(), 2.into());
+    assert_eq!(account.held(), 3.into());
+    assert_eq!(account.total(), 5.into());
+
+    assert_eq!(account.resolve(Resolve::new(TxId::new(2), client)), Ok(()));
+    assert_eq!(account.available(), 2.into());
+    assert_eq!(account.held(), 0.into());
+    assert_eq!(account.total(), 2.into());
+  }
+
+  #[test]
+  fn withdraw_dispute_chargeback() {
+    let client = ClientId::new(1);
+    let mut account = Account::new(client);
+
+    let tx = Deposit::new(TxId::new(1), client, 5.into()).unwrap();
+    assert_eq!(account.deposit(tx), Ok(()));
+    let tx = Withdraw::new(TxId::new(2), client, 5.into()).unwrap();
+    assert_eq!(account.withdraw(tx), Ok(()));
+
+    let tx = Dispute::new(TxId::new(2), client, None).unwrap();
+    assert_eq!(account.dispute(tx, DisputePolicy::Reject), Ok(()));
+    assert_eq!(account.available(), 0.into());
+    assert_eq!(account.held(), 5.into());
+
+    assert_eq!(account.chargeback(Chargeback::new(TxId::new(2), client)), Ok(()));
+    assert_eq!(account.available(), 5.into());
+    assert_eq!(account.held(), 0.into());
+    assert_eq!(account.total(), 5.into());
+  }
+
+  fn spent_deposit() -> Account {
+    let client = ClientId::new(1);
+    let mut account = Account::new(client);
+
+    let tx = Deposit::new(TxId::new(1), client, 5.into()).unwrap();
+    assert_eq!(account.deposit(tx), Ok(()));
+    let tx = Withdraw::new(TxId::new(2), client, 3.into()).unwrap();
+    assert_eq!(account.withdraw(tx), Ok(()));
+
+    account
+  }
+
+  #[test]
+  fn dispute_policies() {
+    let client = ClientId::new(1);
+    let tx = Dispute::new(TxId::new(1), client, None).unwrap();
+
+    let mut account = spent_deposit();
+    assert_eq!(account.dispute(tx, DisputePolicy::Reject), Err(TxErrKind::Insufficient));
+    assert_eq!(account.available(), 2.into());
+    assert_eq!(account.held(), 0.into());
+
+    let mut account = spent_deposit();
+    assert_eq!(account.dispute(tx, DisputePolicy::AllowNegative), Ok(()));
+    assert_eq!(account.available(), (-3).into());
+    assert_eq!(account.held(), 5.into());
+    assert_eq!(account.debt(), 0.into());
+
+    let mut account = spent_deposit();
+    assert_eq!(account.dispute(tx, DisputePolicy::HoldAvailable), Ok(()));
+    assert_eq!(account.available(), 0.into());
+    assert_eq!(account.held(), 2.into());
+    assert_eq!(account.debt(), 3.into());
+
+    assert_eq!(account.resolve(Resolve::new(TxId::new(1), client)), Ok(()));
+    assert_eq!(account.available(), 2.into());
+    assert_eq!(account.held(), 0.into());
+    assert_eq!(account.debt(), 0.into());
+  }
 }
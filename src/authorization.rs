@@ -0,0 +1,94 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{ClientId, TxErrKind, TxId};
+use derive_more::Display;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// An authorization holds funds for a later capture, e.g. a card payment that is
+/// authorized first and settled afterwards.
+///
+/// An authorization must move its amount from the available funds to the authorized
+/// funds, which are kept apart from the funds held by disputes.
+///
+/// # Errors
+///
+/// * An error is thrown if the [client ID](ClientId) and account do not already exist.
+///
+/// * An error is thrown if the [transaction ID](TxId) has already been used.
+///
+/// * An error is thrown if the amount is negative.
+///
+/// * An error is thrown if the amount being authorized is more than the available balance
+///   in the client's account (see [credit limits](crate::CreditLimit)).
+///
+/// # Notes
+///
+/// * An authorization is closed by a [capture](crate::Capture), which turns the captured
+///   amount into a [withdrawal](crate::Withdraw) under the authorization's transaction ID
+///   and releases the rest, or by a [void](crate::Void), which releases all of it.
+///
+/// * An authorization that expires is released once a transaction with a later timestamp
+///   is applied (see [Db::clock](crate::Db::clock)), and can no longer be captured or
+///   voided.
+#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[display(fmt = "Authorization {} {} Amount={}", id, client, amount)]
+pub struct Authorization {
+  id: TxId,
+  client: ClientId,
+  amount: Decimal,
+  expires: Option<u64>,
+}
+
+impl Authorization {
+  pub fn new(
+    id: TxId,
+    client: ClientId,
+    amount: Decimal,
+    expires: Option<u64>,
+  ) -> Result<Self, TxErrKind> {
+    if amount.is_sign_negative() {
+      Err(TxErrKind::NegativeAmount)
+    } else {
+      Ok(Self { id, client, amount, expires })
+    }
+  }
+
+  /// Get the authorization's id.
+  pub fn id(&self) -> TxId {
+    self.id
+  }
+
+  /// Get the authorization's client.
+  pub fn client(&self) -> ClientId {
+    self.client
+  }
+
+  /// Get the authorization's amount.
+  pub fn amount(&self) -> Decimal {
+    self.amount
+  }
+
+  /// Get when the authorization expires, in seconds since the Unix epoch, if it does.
+  pub fn expires(&self) -> Option<u64> {
+    self.expires
+  }
+
+  /// Whether the authorization has expired by *now*.
+  pub fn is_expired(&self, now: u64) -> bool {
+    self.expires.is_some_and(|expires| expires <= now)
+  }
+}
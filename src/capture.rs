@@ -0,0 +1,86 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{ClientId, TxErrKind, TxId};
+use derive_more::Display;
+use rust_decimal::Decimal;
+
+/// A capture settles an [authorization](crate::Authorization).
+///
+/// A capture must release the authorized funds, turning the captured part into a
+/// [withdrawal](crate::Withdraw) and crediting the rest back to the available funds.
+///
+/// # Errors
+///
+/// * An error is thrown if the [client ID](ClientId) and account do not already exist.
+///
+/// * An error is thrown if the [transaction ID](TxId) does not already exist.
+///
+/// * An error is thrown if the [transaction ID](TxId) does not refer to an open
+///   authorization associated with [the corresponding client](ClientId).
+///
+/// * An error is thrown if the captured amount is negative or larger than the authorized
+///   amount.
+///
+/// * An error is thrown if the authorization has expired by the capture's timestamp, or
+///   has been released by the [database's clock](crate::Db::clock).
+///
+/// # Notes
+///
+/// * A capture without an amount captures the whole authorization.
+///
+/// * A capture without a timestamp cannot be checked against the authorization's expiry.
+#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Copy)]
+#[display(fmt = "Capture {} {} Amount={:?}", id, client, amount)]
+pub struct Capture {
+  id: TxId,
+  client: ClientId,
+  amount: Option<Decimal>,
+  timestamp: Option<u64>,
+}
+
+impl Capture {
+  pub fn new(
+    id: TxId,
+    client: ClientId,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+  ) -> Result<Self, TxErrKind> {
+    match amount {
+      Some(amount) if amount.is_sign_negative() => Err(TxErrKind::NegativeAmount),
+      _ => Ok(Self { id, client, amount, timestamp }),
+    }
+  }
+
+  /// Get the capture's id.
+  pub fn id(&self) -> TxId {
+    self.id
+  }
+
+  /// Get the capture's client.
+  pub fn client(&self) -> ClientId {
+    self.client
+  }
+
+  /// Get the captured amount, if only part of the authorization is captured.
+  pub fn amount(&self) -> Option<Decimal> {
+    self.amount
+  }
+
+  /// Get when the capture was issued, in seconds since the Unix epoch.
+  pub fn timestamp(&self) -> Option<u64> {
+    self.timestamp
+  }
+}
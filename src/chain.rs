@@ -0,0 +1,198 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::Tx;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use sha2::{Digest as _, Sha256};
+use std::fmt;
+use std::str::FromStr;
+
+/// A SHA-256 digest in the [hash chain](Chain).
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Default)]
+pub struct Digest([u8; 32]);
+
+impl fmt::Display for Digest {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    for byte in &self.0 {
+      write!(f, "{:02x}", byte)?;
+    }
+
+    Ok(())
+  }
+}
+
+impl FromStr for Digest {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let s = s.trim();
+
+    if s.len() != 64 || !s.is_ascii() {
+      return Err(format!("Invalid digest `{}`: expected 64 hexadecimal digits", s));
+    }
+
+    let mut digest = [0; 32];
+    for (i, byte) in digest.iter_mut().enumerate() {
+      *byte = u8::from_str_radix(&s[i * 2..i * 2 + 2], 16)
+        .map_err(|e| format!("Invalid digest `{}`: {}", s, e))?;
+    }
+
+    Ok(Digest(digest))
+  }
+}
+
+impl Serialize for Digest {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl<'de> Deserialize<'de> for Digest {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let s = String::deserialize(deserializer)?;
+    s.parse().map_err(serde::de::Error::custom)
+  }
+}
+
+/// A tamper-evident hash chain over applied transactions.
+///
+/// Each applied transaction is folded into the digest of the previous one (starting with
+/// an all-zero digest), such that the final head digest depends on every transaction and
+/// on their order. Rewriting, inserting, removing or reordering any transaction changes
+/// the head.
+///
+/// # Notes
+///
+/// * Amounts are normalized before hashing, so `1.5` and `1.5000` hash the same.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct Chain {
+  head: Digest,
+  len: u64,
+}
+
+impl Chain {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Get the digest of the last folded transaction.
+  pub fn head(&self) -> Digest {
+    self.head
+  }
+
+  /// Get the number of folded transactions.
+  pub fn len(&self) -> u64 {
+    self.len
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.len == 0
+  }
+
+  /// Fold *tx* into the chain, returning the new head.
+  pub fn fold(&mut self, tx: &Tx) -> Digest {
+    fn field(hasher: &mut Sha256, value: &[u8]) {
+      // Length-prefix every field so that no two transactions encode the same way.
+      hasher.update((value.len() as u64).to_be_bytes());
+      hasher.update(value);
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(self.head.0);
+    field(&mut hasher, tx.typ.to_string().as_bytes());
+    field(&mut hasher, &tx.client.to_be_bytes());
+    field(&mut hasher, &tx.tx.to_be_bytes());
+
+    let amount = tx.amount.map(|amount| amount.normalize().to_string());
+    field(&mut hasher, amount.as_deref().unwrap_or_default().as_bytes());
+    field(&mut hasher, tx.operator.as_deref().unwrap_or_default().as_bytes());
+    field(&mut hasher, tx.reason.as_deref().unwrap_or_default().as_bytes());
+
+    // Only transfers have a destination, leaving the digests of other transactions as
+    // they were before transfers existed.
+    if let Some(destination) = tx.destination {
+      field(&mut hasher, &destination.to_be_bytes());
+    }
+
+    // Likewise, any transaction with a timestamp hashes it, so that only the digests of
+    // rows that carry the column differ from before timestamps existed.
+    if let Some(timestamp) = tx.timestamp {
+      field(&mut hasher, &timestamp.to_be_bytes());
+    }
+
+    self.head = Digest(hasher.finalize().into());
+    self.len += 1;
+    self.head
+  }
+}
+
+#[cfg(test)]
+mod chain_tests {
+  use crate::{Chain, Digest, Tx};
+  use rust_decimal::Decimal;
+
+  #[test]
+  fn order_matters() {
+    let tx1 = Tx::new_deposit(1, 1, Decimal::from(5));
+    let tx2 = Tx::new_withdraw(2, 1, Decimal::from(3));
+
+    let mut chain1 = Chain::new();
+    chain1.fold(&tx1);
+    chain1.fold(&tx2);
+
+    let mut chain2 = Chain::new();
+    chain2.fold(&tx2);
+    chain2.fold(&tx1);
+
+    assert_eq!(chain1.len(), 2);
+    assert_ne!(chain1.head(), chain2.head());
+  }
+
+  #[test]
+  fn normalized_amounts() {
+    let mut chain1 = Chain::new();
+    chain1.fold(&Tx::new_deposit(1, 1, Decimal::new(15, 1)));
+
+    let mut chain2 = Chain::new();
+    chain2.fold(&Tx::new_deposit(1, 1, Decimal::new(15000, 4)));
+
+    assert_eq!(chain1.head(), chain2.head());
+  }
+
+  #[test]
+  fn timestamps() {
+    let deposit = Tx::new_deposit(1, 1, Decimal::from(5));
+
+    let mut chain1 = Chain::new();
+    chain1.fold(&deposit);
+
+    let mut chain2 = Chain::new();
+    chain2.fold(&deposit.clone().with_timestamp(60));
+
+    let mut chain3 = Chain::new();
+    chain3.fold(&deposit.with_timestamp(120));
+
+    assert_ne!(chain1.head(), chain2.head());
+    assert_ne!(chain2.head(), chain3.head());
+  }
+
+  #[test]
+  fn digest_roundtrip() {
+    let mut chain = Chain::new();
+    let head = chain.fold(&Tx::new_deposit(1, 1, Decimal::from(5)));
+    assert_eq!(head.to_string().parse::<Digest>(), Ok(head));
+    assert!("abc".parse::<Digest>().is_err());
+  }
+}
@@ -19,7 +19,11 @@ use derive_new::new;
 
 /// A chargeback is a resolution of a dispute by reversing a transaction.
 ///
-/// A chargeback must decrease the held funds without increasing the available funds.
+/// A chargeback of a disputed deposit must decrease the held funds without increasing the
+/// available funds.
+///
+/// A chargeback of a disputed withdrawal must move the held funds back to the available
+/// funds, crediting the client back.
 ///
 /// # Errors
 ///
@@ -28,7 +32,7 @@ use derive_new::new;
 /// * An error is thrown if the [transaction ID](TxId) does not already exist.
 ///
 /// * An error is thrown if the [transaction ID](TxId) does not refer to a held deposit
-///   transaction associated with [the corresponding client](ClientId).
+///   or withdrawal transaction associated with [the corresponding client](ClientId).
 ///
 /// # Notes
 ///
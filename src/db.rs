@@ -13,204 +13,2293 @@
 
 #![warn(clippy::all)]
 
+use crate::velocity::{Withdrawal, DAY};
 use crate::{
-  Account, AccountLocked, Chargeback, ClientId, Deposit, Dispute, Resolve, Tx, TxErr,
-  TxId, TxResult, TxType, Withdraw,
+  Account, AnyAccount, Authorization, Capture, Chain, Chargeback, ClientId, CreditLimit,
+  Deposit, Dispute, DisputePolicy, Effect, FeeSchedule, JournalEntry, Observer,
+  OpeningBalance, Outcome, PrecisionPolicy, Release, Resolve, Snapshot, SnapshotErr,
+  Transaction, Transfer, Transition, Tx, TxErr, TxErrKind, TxId, TxResult, TxState,
+  TxType, Unlock, VelocityLimits, Void, Withdraw, SNAPSHOT_VERSION,
 };
 use derive_new::new;
 use rust_decimal::Decimal;
 use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+
+/// A boxed [Observer], only so that [Db] can still derive [Debug].
+struct ObserverBox(Box<dyn Observer>);
+
+impl fmt::Debug for ObserverBox {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    f.write_str("Observer")
+  }
+}
+
+/// The clock and the accounts it expired authorizations in, as they were before a
+/// transaction advanced it, so that it can be put back if the transaction fails.
+struct Rewind {
+  clock: Option<u64>,
+  next_expiry: Option<u64>,
+  accounts: Vec<(ClientId, AnyAccount)>,
+
+  /// The outcomes of the expiries, reported only once the transaction is applied.
+  expiries: Vec<Outcome>,
+}
+
+/// An account as it was before a transaction, to work out the transaction's effect on
+/// it.
+struct Prior {
+  client: ClientId,
+  created: bool,
+  funds: (Decimal, Decimal, Decimal),
+  locked: bool,
+  state: Option<TxState>,
+}
 
 /// Database of accounts.
 #[derive(Debug, new)]
 pub struct Db {
   #[new(default)]
-  accounts: HashMap<ClientId, Account>,
+  observer: Option<ObserverBox>,
 
   #[new(default)]
-  accounts_locked: HashMap<ClientId, Account<AccountLocked>>,
+  accounts: HashMap<ClientId, AnyAccount>,
 
   #[new(default)]
   tx_ids: HashSet<TxId>,
+
+  #[new(default)]
+  transfers: HashMap<TxId, Transfer>,
+
+  #[new(default)]
+  dispute_policy: DisputePolicy,
+
+  #[new(default)]
+  precision_policy: PrecisionPolicy,
+
+  #[new(default)]
+  reject_zero_amounts: bool,
+
+  #[new(default)]
+  fees: Option<FeeSchedule>,
+
+  #[new(default)]
+  velocity: Option<VelocityLimits>,
+
+  /// Recent withdrawals of every client, counted towards the velocity limits.
+  #[new(default)]
+  withdrawals: HashMap<ClientId, Vec<Withdrawal>>,
+
+  /// How long authorizations stay open, in seconds.
+  #[new(default)]
+  authorization_ttl: Option<u64>,
+
+  /// The latest timestamp of an applied transaction.
+  #[new(default)]
+  clock: Option<u64>,
+
+  /// The earliest expiry of an open authorization, or earlier, to skip needless sweeps.
+  #[new(default)]
+  next_expiry: Option<u64>,
+
+  #[new(default)]
+  chain: Chain,
+
+  #[new(default)]
+  journal: bool,
 }
 
 impl Db {
-  pub fn accounts(&self) -> impl Iterator<Item = &Account> {
-    self.accounts.values()
+  /// Set the policy to apply when a deposit is disputed but its amount is not available.
+  pub fn with_dispute_policy(mut self, policy: DisputePolicy) -> Self {
+    self.dispute_policy = policy;
+    self
+  }
+
+  pub fn dispute_policy(&self) -> DisputePolicy {
+    self.dispute_policy
+  }
+
+  /// Set the policy to apply when an amount has more than [MAX_SCALE](crate::MAX_SCALE)
+  /// decimal places.
+  pub fn with_precision_policy(mut self, policy: PrecisionPolicy) -> Self {
+    self.precision_policy = policy;
+    self
+  }
+
+  pub fn precision_policy(&self) -> PrecisionPolicy {
+    self.precision_policy
+  }
+
+  /// Reject deposits and withdrawals of a zero amount.
+  pub fn with_reject_zero_amounts(mut self, reject: bool) -> Self {
+    self.reject_zero_amounts = reject;
+    self
+  }
+
+  /// Charge fees on withdrawals and chargebacks according to a fee schedule.
+  pub fn with_fee_schedule(mut self, fees: FeeSchedule) -> Self {
+    self.fees = Some(fees);
+    self
+  }
+
+  pub fn fee_schedule(&self) -> Option<&FeeSchedule> {
+    self.fees.as_ref()
+  }
+
+  /// Limit the amount and number of withdrawals per client over time.
+  pub fn with_velocity_limits(mut self, limits: VelocityLimits) -> Self {
+    self.velocity = Some(limits);
+    self
   }
 
-  pub fn accounts_locked(&self) -> impl Iterator<Item = &Account<AccountLocked>> {
-    self.accounts_locked.values()
+  pub fn velocity_limits(&self) -> Option<&VelocityLimits> {
+    self.velocity.as_ref()
   }
 
-  pub fn get_account(&self, id: ClientId) -> Option<&Account> {
+  /// Expire authorizations *ttl* seconds after their timestamp. Authorizations without a
+  /// timestamp never expire.
+  ///
+  /// Expired authorizations are released before executing the first transaction with a
+  /// timestamp at or after their expiry (see [clock](Db::clock)), and kept if it fails,
+  /// so that expiries follow from the applied transactions alone and are reproduced by
+  /// replaying them.
+  pub fn with_authorization_ttl(mut self, ttl: u64) -> Self {
+    self.authorization_ttl = Some(ttl);
+    self
+  }
+
+  pub fn authorization_ttl(&self) -> Option<u64> {
+    self.authorization_ttl
+  }
+
+  /// The latest timestamp of an applied transaction, by which expired authorizations have
+  /// been released.
+  pub fn clock(&self) -> Option<u64> {
+    self.clock
+  }
+
+  /// Keep a journal of applied transactions in every account.
+  pub fn with_journal(mut self, journal: bool) -> Self {
+    self.journal = journal;
+    self
+  }
+
+  /// Notify an observer of every processed transaction.
+  pub fn with_observer(mut self, observer: Box<dyn Observer>) -> Self {
+    self.observer = Some(ObserverBox(observer));
+    self
+  }
+
+  /// The hash chain over all successfully applied transactions.
+  pub fn chain(&self) -> &Chain {
+    &self.chain
+  }
+
+  /// All accounts, locked or unlocked.
+  pub fn accounts(&self) -> impl Iterator<Item = &AnyAccount> {
+    self.accounts.values()
+  }
+
+  pub fn get_account(&self, id: ClientId) -> Option<&AnyAccount> {
     self.accounts.get(&id)
   }
 
-  pub fn process(&mut self, tx: &Tx) -> TxResult {
-    fn ensure_amount(tx: &Tx) -> Result<Decimal, TxErr> {
-      match tx.amount {
-        Some(amount) => Ok(amount),
-        None => Err(TxErr::MissingAmount),
+  /// Take a snapshot of the database's state.
+  pub fn snapshot(&self) -> Snapshot {
+    let mut accounts: Vec<_> = self.accounts.values().map(AnyAccount::snapshot).collect();
+    accounts.sort_by_key(|account| account.id());
+
+    let mut tx_ids: Vec<_> = self.tx_ids.iter().copied().collect();
+    tx_ids.sort();
+
+    let mut transfers: Vec<_> = self.transfers.values().copied().collect();
+    transfers.sort_by_key(|transfer| transfer.id());
+
+    let mut withdrawals: Vec<_> = self.withdrawals.values().flatten().copied().collect();
+    withdrawals.sort_by_key(|withdrawal| (withdrawal.client, withdrawal.timestamp));
+
+    Snapshot {
+      version: SNAPSHOT_VERSION,
+      accounts,
+      tx_ids,
+      transfers,
+      withdrawals,
+      clock: self.clock,
+      chain: self.chain,
+    }
+  }
+
+  /// Create a database from a snapshot of its state, with a default configuration.
+  pub fn restore(snapshot: Snapshot) -> Result<Self, SnapshotErr> {
+    if snapshot.version != SNAPSHOT_VERSION {
+      return Err(SnapshotErr::Version(snapshot.version));
+    }
+
+    let mut db = Db::new();
+    db.chain = snapshot.chain;
+    db.clock = snapshot.clock;
+    db.tx_ids = snapshot.tx_ids.into_iter().collect();
+    db.transfers =
+      snapshot.transfers.into_iter().map(|transfer| (transfer.id(), transfer)).collect();
+
+    for withdrawal in snapshot.withdrawals {
+      db.withdrawals.entry(withdrawal.client).or_default().push(withdrawal);
+    }
+
+    for account in snapshot.accounts {
+      let id = account.id();
+
+      if db.accounts.insert(id, AnyAccount::restore(account)).is_some() {
+        return Err(SnapshotErr::Invalid(format!("Duplicate account for {}", id)));
+      }
+    }
+
+    db.next_expiry = db.accounts.values().filter_map(AnyAccount::next_expiry).min();
+
+    Ok(db)
+  }
+
+  /// Create an account from an opening balance.
+  pub fn open_account(&mut self, balance: &OpeningBalance) -> TxResult {
+    let client = ClientId::new(balance.client);
+    let err = |kind| TxErr::new(kind).with_client(client);
+
+    if self.accounts.contains_key(&client) {
+      return Err(err(TxErrKind::AccountExists));
+    }
+
+    if balance.held.is_sign_negative() || balance.debt.is_sign_negative() {
+      return Err(err(TxErrKind::NegativeAmount));
+    }
+
+    if balance.authorized.is_sign_negative() {
+      return Err(err(TxErrKind::NegativeAmount));
+    }
+
+    if !balance.authorized.is_zero() {
+      return Err(err(TxErrKind::PendingAuthorizations));
+    }
+
+    let total = balance.available.checked_add(balance.held);
+
+    match total.and_then(|total| total.checked_add(balance.authorized)) {
+      Some(total) if total == balance.total => {}
+      Some(_) => return Err(err(TxErrKind::InconsistentTotal)),
+      None => return Err(err(TxErrKind::Overflow)),
+    }
+
+    let account = Account::open(client, balance.available, balance.held, balance.debt);
+
+    if balance.locked {
+      self.accounts.insert(client, account.lock().into());
+    } else {
+      self.accounts.insert(client, account.into());
+    }
+
+    Ok(())
+  }
+
+  /// Set a client's credit limit, creating their account if needed.
+  pub fn set_credit_limit(&mut self, limit: &CreditLimit) -> TxResult {
+    let client = ClientId::new(limit.client);
+
+    self
+      .credit_limit(client, limit.limit)
+      .map_err(|kind| TxErr::new(kind).with_client(client))
+  }
+
+  /// Advance the clock to *timestamp* if it is later, then release the authorizations
+  /// that have expired by the clock (including any authorized with an earlier timestamp).
+  fn advance_clock(&mut self, timestamp: u64) -> Rewind {
+    let mut rewind = Rewind {
+      clock: self.clock,
+      next_expiry: self.next_expiry,
+      accounts: Vec::new(),
+      expiries: Vec::new(),
+    };
+
+    let now = self.clock.map_or(timestamp, |clock| clock.max(timestamp));
+    self.clock = Some(now);
+
+    if self.next_expiry.is_some_and(|expiry| expiry <= now) {
+      (rewind.accounts, rewind.expiries) = self.expire_authorizations(now);
+    }
+
+    rewind
+  }
+
+  /// Put the clock and the accounts it expired authorizations in back as they were.
+  fn rewind_clock(&mut self, rewind: Rewind) {
+    self.clock = rewind.clock;
+    self.next_expiry = rewind.next_expiry;
+    self.accounts.extend(rewind.accounts);
+  }
+
+  /// Release the authorizations that have expired by *now* in every account.
+  ///
+  /// Expiries are recorded in the journals and reported to the observer, but are not
+  /// transactions: they are not folded into the hash chain. They follow from the
+  /// timestamps of the transactions that are.
+  ///
+  /// Returns the accounts that had authorizations expire, as they were before, and the
+  /// outcome of every expiry (sorted by client and authorization).
+  fn expire_authorizations(
+    &mut self,
+    now: u64,
+  ) -> (Vec<(ClientId, AnyAccount)>, Vec<Outcome>) {
+    let mut expiring = Vec::new();
+    let mut expiries = Vec::new();
+
+    for (client, account) in self.accounts.iter_mut() {
+      if account.next_expiry().is_none_or(|expiry| expiry > now) {
+        continue;
+      }
+
+      expiring.push((*client, account.clone()));
+      let mut funds = account.funds();
+      let locked = account.is_locked();
+
+      for authorization in account.expire(now) {
+        let amount = authorization.amount();
+        let released = (funds.0 + amount, funds.1, funds.2 - amount);
+
+        if self.journal {
+          account.record(JournalEntry::expiry(authorization.id(), funds, released));
+        }
+
+        let effect = Effect::new(*client, false, funds, released, None, (locked, locked));
+        expiries.push(Outcome::new(authorization.id(), TxType::Authorize, vec![effect]));
+        funds = released;
+      }
+    }
+
+    self.next_expiry = self.accounts.values().filter_map(AnyAccount::next_expiry).min();
+    expiries.sort_by_key(|outcome| (outcome.client(), outcome.tx()));
+    (expiring, expiries)
+  }
+
+  /// Notify the observer of the outcomes of expired authorizations.
+  fn expired(&mut self, expiries: &[Outcome]) {
+    if let Some(observer) = self.observer.as_mut() {
+      for outcome in expiries {
+        observer.0.expired(outcome);
+      }
+    }
+  }
+
+  /// The account of *client* as it is before transaction *id*.
+  fn prior(&self, client: ClientId, id: TxId) -> Prior {
+    match self.accounts.get(&client) {
+      Some(account) => Prior {
+        client,
+        created: false,
+        funds: account.funds(),
+        locked: account.is_locked(),
+        state: account.tx_state(id),
+      },
+      None => Prior {
+        client,
+        created: true,
+        funds: (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO),
+        locked: false,
+        state: None,
+      },
+    }
+  }
+
+  /// The effect of transaction *id* of type *typ* on an account that was as *prior*
+  /// before it.
+  fn effect(&self, prior: &Prior, id: TxId, typ: TxType) -> Effect {
+    let account =
+      self.accounts.get(&prior.client).expect("Missing account after transaction");
+
+    let transition = match account.tx_state(id) {
+      Some(to) if typ != TxType::Unlock => Some(Transition { from: prior.state, to }),
+      _ => None,
+    };
+
+    Effect::new(
+      prior.client,
+      prior.created,
+      prior.funds,
+      account.funds(),
+      transition,
+      (prior.locked, account.is_locked()),
+    )
+  }
+
+  /// Get an unlocked account for modification.
+  fn unlocked_account_mut(
+    &mut self,
+    client: ClientId,
+  ) -> Result<&mut Account, TxErrKind> {
+    match self.accounts.get_mut(&client) {
+      Some(AnyAccount::Unlocked(account)) => Ok(account),
+      Some(AnyAccount::Locked(_)) => Err(TxErrKind::AccountLocked),
+      None => Err(TxErrKind::AccessUnavailable),
+    }
+  }
+
+  /// Process a raw transaction record, see [apply](Db::apply).
+  pub fn process(&mut self, tx: &Tx) -> Result<Outcome, TxErr> {
+    match Transaction::try_from(tx) {
+      Ok(transaction) => self.apply(transaction),
+      Err(kind) => {
+        let err = self.error(kind, TxId::new(tx.tx), ClientId::new(tx.client), tx.amount);
+        Err(self.rejected(err))
+      }
+    }
+  }
+
+  /// Apply a transaction, folding it into the hash chain (and recording it in the journal
+  /// of its account) if it succeeds.
+  pub fn apply(&mut self, tx: Transaction) -> Result<Outcome, TxErr> {
+    let client = tx.client();
+    let id = tx.tx();
+
+    // Authorizations that have expired by the timestamp are released before executing the
+    // transaction, but only for good if it is applied: the clock follows the applied
+    // transactions alone, so that replaying them reproduces the expiries.
+    let rewind = tx.timestamp().map(|timestamp| self.advance_clock(timestamp));
+
+    // The other party of a transfer, or of the transfer a transaction refers to.
+    let counterparty = match &tx {
+      Transaction::Transfer { destination, .. } => Some(*destination),
+      _ => self.transfers.get(&id).and_then(|transfer| transfer.counterparty(client)),
+    };
+
+    let mut priors = vec![self.prior(client, id)];
+    priors.extend(counterparty.map(|counterparty| self.prior(counterparty, id)));
+
+    // The fee account might not be a party of the transaction at all.
+    let fee_prior = match &self.fees {
+      Some(fees) if fees.account() != client && Some(fees.account()) != counterparty => {
+        Some(self.prior(fees.account(), id))
+      }
+      _ => None,
+    };
+
+    let (payer, fee) = match self.execute(&tx) {
+      Ok(fee) => fee,
+      Err(kind) => {
+        if let Some(rewind) = rewind {
+          self.rewind_clock(rewind);
+        }
+
+        let err = self.error(kind, id, client, tx.amount());
+        return Err(self.rejected(err));
+      }
+    };
+
+    self.chain.fold(&Tx::from(&tx));
+
+    let fee_account = match &self.fees {
+      Some(fees) if !fee.is_zero() => Some(fees.account()),
+      _ => None,
+    };
+
+    let fee_prior = fee_prior.filter(|_| fee_account.is_some());
+
+    if self.journal {
+      for prior in &priors {
+        let party = prior.client;
+        let account =
+          self.accounts.get_mut(&party).expect("Missing account after transaction");
+
+        let after = account.funds();
+
+        // A fee paid or collected by a party gets an entry of its own.
+        let mut charged = after;
+
+        if party == payer {
+          charged.0 += fee;
+        }
+
+        if fee_account == Some(party) {
+          charged.0 -= fee;
+        }
+
+        account.record(JournalEntry::new(id, tx.typ(), prior.funds, charged));
+
+        if !fee.is_zero() && (party == payer || fee_account == Some(party)) {
+          account.record(JournalEntry::fee(id, tx.typ(), charged, after));
+        }
+      }
+
+      if let Some(prior) = &fee_prior {
+        let account = self.accounts.get_mut(&prior.client).expect("Missing fee account");
+        account.record(JournalEntry::fee(id, tx.typ(), prior.funds, account.funds()));
       }
     }
 
-    fn ensure_no_amount(tx: &Tx) -> TxResult {
-      match tx.amount {
-        Some(_) => Err(TxErr::ExtraneousAmount),
-        None => Ok(()),
+    let effects = priors
+      .iter()
+      .chain(&fee_prior)
+      .map(|prior| self.effect(prior, id, tx.typ()))
+      .collect();
+    let outcome = Outcome::new(id, tx.typ(), effects);
+
+    // Authorizations released before the transaction are only reported once it applied.
+    if let Some(rewind) = rewind {
+      self.expired(&rewind.expiries);
+    }
+
+    if let Some(observer) = self.observer.as_mut() {
+      observer.0.applied(&outcome);
+    }
+
+    // An authorization that is already expired by the clock is released right away.
+    if let Some(now) = self.clock {
+      if self.next_expiry.is_some_and(|expiry| expiry <= now) {
+        let (_, expiries) = self.expire_authorizations(now);
+        self.expired(&expiries);
       }
     }
 
-    let id = TxId::new(tx.tx);
-    let client = ClientId::new(tx.client);
+    Ok(outcome)
+  }
+
+  /// Notify the observer of a rejected transaction.
+  fn rejected(&mut self, err: TxErr) -> TxErr {
+    if let Some(observer) = self.observer.as_mut() {
+      observer.0.rejected(&err);
+    }
+
+    err
+  }
+
+  /// An error for a transaction, along with the current balances of its account.
+  fn error(
+    &self,
+    kind: TxErrKind,
+    tx: TxId,
+    client: ClientId,
+    amount: Option<Decimal>,
+  ) -> TxErr {
+    let err = TxErr::new(kind).with_tx(tx, client, amount);
+
+    match self.accounts.get(&client) {
+      Some(account) => err.with_balances(account.available(), account.held()),
+      None => err,
+    }
+  }
+
+  /// Check the amount of a deposit or withdrawal against the precision policy and, if
+  /// configured, reject a zero amount.
+  fn amount(&self, amount: Decimal) -> Result<Decimal, TxErrKind> {
+    let amount = self.precision_policy.apply(amount)?;
+
+    if self.reject_zero_amounts && amount.is_zero() {
+      return Err(TxErrKind::ZeroAmount);
+    }
+
+    Ok(amount)
+  }
+
+  /// Apply a transaction to the accounts, returning the client that was charged a fee
+  /// (which is not always the client of the transaction) and the fee.
+  fn execute(&mut self, tx: &Transaction) -> Result<(ClientId, Decimal), TxErrKind> {
+    let client = tx.client();
 
-    match tx.typ {
-      TxType::Deposit => {
-        let amount = ensure_amount(tx)?;
-        self.deposit(id, client, amount)
+    match tx {
+      Transaction::Deposit { tx, client, amount, .. } => {
+        let amount = self.amount(*amount)?;
+        self.deposit(*tx, *client, amount)?;
+      }
+      Transaction::Withdrawal { tx, client, amount, timestamp } => {
+        let amount = self.amount(*amount)?;
+        return Ok((*client, self.withdraw(*tx, *client, amount, *timestamp)?));
+      }
+      Transaction::Dispute { tx, client, amount, .. } => {
+        let amount =
+          amount.map(|amount| self.precision_policy.apply(amount)).transpose()?;
+        self.dispute(*tx, *client, amount)?;
+      }
+      Transaction::Resolve { tx, client, .. } => self.resolve(*tx, *client)?,
+      Transaction::Chargeback { tx, client, .. } => return self.chargeback(*tx, *client),
+      Transaction::Unlock { tx, client, operator, reason, .. } => {
+        self.unlock(*tx, *client, operator.clone(), reason.clone())?;
+      }
+      Transaction::Transfer { tx, client, destination, amount, timestamp } => {
+        let amount = self.amount(*amount)?;
+        self.transfer(*tx, *client, *destination, amount, *timestamp)?;
       }
-      TxType::Withdrawal => {
-        let amount = ensure_amount(tx)?;
-        self.withdraw(id, client, amount)
+      Transaction::Limit { tx, client, amount, operator, reason, .. } => {
+        let amount = self.precision_policy.apply(*amount)?;
+        self.limit(*tx, *client, amount, operator, reason)?;
       }
-      TxType::Dispute => {
-        ensure_no_amount(tx)?;
-        self.dispute(id, client)
+      Transaction::Authorize { tx, client, amount, timestamp } => {
+        let amount = self.amount(*amount)?;
+        self.authorize(*tx, *client, amount, *timestamp)?;
       }
-      TxType::Resolve => {
-        ensure_no_amount(tx)?;
-        self.resolve(id, client)
+      Transaction::Capture { tx, client, amount, timestamp } => {
+        let amount =
+          amount.map(|amount| self.precision_policy.apply(amount)).transpose()?;
+        return Ok((*client, self.capture(*tx, *client, amount, *timestamp)?));
       }
-      TxType::Chargeback => {
-        ensure_no_amount(tx)?;
-        self.chargeback(id, client)
+      Transaction::Void { tx, client, .. } => self.void(*tx, *client)?,
+      Transaction::Release { tx, client, amount, operator, reason, .. } => {
+        let amount =
+          amount.map(|amount| self.precision_policy.apply(amount)).transpose()?;
+        self.release(*tx, *client, amount, operator.clone(), reason.clone())?;
       }
     }
+
+    Ok((client, Decimal::ZERO))
+  }
+
+  /// The fee for a transaction of type *typ* and amount *amount* by *client*, which is
+  /// zero without a fee schedule and for the fee account itself.
+  fn fee(
+    &self,
+    typ: TxType,
+    client: ClientId,
+    amount: Decimal,
+  ) -> Result<Decimal, TxErrKind> {
+    match &self.fees {
+      Some(fees) if fees.account() != client => fees.fee(typ, amount),
+      _ => Ok(Decimal::ZERO),
+    }
+  }
+
+  /// Check that *fee* can be moved from *client*'s account to the fee account without an
+  /// overflow.
+  fn check_fee(&self, client: ClientId, fee: Decimal) -> Result<(), TxErrKind> {
+    if let Some(account) = self.accounts.get(&client) {
+      if account.available().checked_sub(fee).is_none() {
+        return Err(TxErrKind::Overflow);
+      }
+    }
+
+    let fee_account = match &self.fees {
+      Some(fees) => self.accounts.get(&fees.account()),
+      None => None,
+    };
+
+    match fee_account {
+      Some(account)
+        if account.total().checked_add(fee).is_none()
+          || account.available().checked_add(fee).is_none() =>
+      {
+        Err(TxErrKind::Overflow)
+      }
+      _ => Ok(()),
+    }
+  }
+
+  /// Move a fee that was checked by [check_fee](Db::check_fee) from *client*'s account to
+  /// the fee account, creating it if needed.
+  fn collect_fee(&mut self, client: ClientId, fee: Decimal) {
+    let fee_account = match &self.fees {
+      Some(fees) if !fee.is_zero() => fees.account(),
+      _ => return,
+    };
+
+    self.accounts.get_mut(&client).expect("Missing account charged a fee").charge(fee);
+    self
+      .accounts
+      .entry(fee_account)
+      .or_insert_with(|| AnyAccount::from(Account::new(fee_account)))
+      .credit(fee);
   }
 
-  fn deposit(&mut self, id: TxId, client: ClientId, amount: Decimal) -> TxResult {
+  fn deposit(
+    &mut self,
+    id: TxId,
+    client: ClientId,
+    amount: Decimal,
+  ) -> Result<(), TxErrKind> {
     let tx = Deposit::new(id, client, amount)?;
 
     if self.tx_ids.contains(&id) {
-      return Err(TxErr::Duplicate);
+      return Err(TxErrKind::Duplicate);
     }
 
-    if let Some(account) = self.accounts.get_mut(&client) {
-      account.deposit(tx)?;
-      self.tx_ids.insert(id);
-    } else {
-      let mut account = Account::new(client);
-      account.deposit(tx)?;
-      self.tx_ids.insert(id);
-      self.accounts.insert(client, account);
+    match self.accounts.get_mut(&client) {
+      Some(AnyAccount::Unlocked(account)) => account.deposit(tx)?,
+      Some(AnyAccount::Locked(_)) => return Err(TxErrKind::AccountLocked),
+      None => {
+        let mut account = Account::new(client);
+        account.deposit(tx)?;
+        self.accounts.insert(client, account.into());
+      }
     }
 
+    self.tx_ids.insert(id);
+
     Ok(())
   }
 
-  fn withdraw(&mut self, id: TxId, client: ClientId, amount: Decimal) -> TxResult {
+  fn withdraw(
+    &mut self,
+    id: TxId,
+    client: ClientId,
+    amount: Decimal,
+    timestamp: Option<u64>,
+  ) -> Result<Decimal, TxErrKind> {
     let tx = Withdraw::new(id, client, amount)?;
 
     if self.tx_ids.contains(&id) {
-      return Err(TxErr::Duplicate);
+      return Err(TxErrKind::Duplicate);
     }
 
-    if let Some(account) = self.accounts.get_mut(&client) {
-      account.withdraw(tx)?;
-      self.tx_ids.insert(id);
-      Ok(())
-    } else {
-      Err(TxErr::AccessUnavailable)
+    let fee = self.fee(TxType::Withdrawal, client, amount)?;
+
+    // The client must be able to pay for both the withdrawal and its fee.
+    let cost = amount.checked_add(fee).ok_or(TxErrKind::Overflow)?;
+    self.unlocked_account_mut(client)?.check_funds(cost)?;
+    self.check_velocity(client, amount, timestamp)?;
+
+    self.check_fee(client, fee)?;
+    self.unlocked_account_mut(client)?.withdraw(tx)?;
+    self.tx_ids.insert(id);
+    self.collect_fee(client, fee);
+    self.count_velocity(client, amount, timestamp);
+
+    Ok(fee)
+  }
+
+  /// Check that *client* can take *amount* out of their account at *timestamp* without
+  /// exceeding the velocity limits, if any.
+  fn check_velocity(
+    &self,
+    client: ClientId,
+    amount: Decimal,
+    timestamp: Option<u64>,
+  ) -> Result<(), TxErrKind> {
+    match &self.velocity {
+      Some(limits) => {
+        let history = self.withdrawals.get(&client).map_or(&[][..], Vec::as_slice);
+        limits.check(client, history, timestamp, amount)
+      }
+      None => Ok(()),
+    }
+  }
+
+  /// Count *amount* taken out of *client*'s account at *timestamp* towards the velocity
+  /// limits, if any.
+  fn count_velocity(
+    &mut self,
+    client: ClientId,
+    amount: Decimal,
+    timestamp: Option<u64>,
+  ) {
+    if let (Some(_), Some(timestamp)) = (&self.velocity, timestamp) {
+      let history = self.withdrawals.entry(client).or_default();
+      history.push(Withdrawal { client, timestamp, amount });
+
+      // Forget the withdrawals that no longer count towards any window. Withdrawals may
+      // be up to a day behind the latest one, and their windows reach back another day.
+      let latest =
+        history.iter().map(|withdrawal| withdrawal.timestamp).max().unwrap_or(timestamp);
+      history.retain(|withdrawal| withdrawal.timestamp.saturating_add(2 * DAY) > latest);
     }
   }
 
-  fn dispute(&mut self, id: TxId, client: ClientId) -> TxResult {
-    let tx = Dispute::new(id, client);
+  fn dispute(
+    &mut self,
+    id: TxId,
+    client: ClientId,
+    amount: Option<Decimal>,
+  ) -> Result<(), TxErrKind> {
+    let tx = Dispute::new(id, client, amount)?;
 
     if !self.tx_ids.contains(&id) {
-      return Err(TxErr::MissingTx);
+      return Err(TxErrKind::MissingTx);
     }
 
-    if let Some(account) = self.accounts.get_mut(&client) {
-      account.dispute(tx)
-    } else {
-      Err(TxErr::AccessUnavailable)
+    let policy = self.dispute_policy;
+
+    // A transfer is disputed as the deposit into its destination, whichever party refers
+    // to it. The source is only credited back once the dispute is charged back.
+    if let Some(transfer) = self.transfer_of(id, client)? {
+      let destination = transfer.destination();
+      let tx = Dispute::new(id, destination, amount)?;
+      return self.unlocked_account_mut(destination)?.dispute(tx, policy);
     }
+
+    self.unlocked_account_mut(client)?.dispute(tx, policy)
   }
 
-  fn resolve(&mut self, id: TxId, client: ClientId) -> TxResult {
+  fn resolve(&mut self, id: TxId, client: ClientId) -> Result<(), TxErrKind> {
     let tx = Resolve::new(id, client);
 
     if !self.tx_ids.contains(&id) {
-      return Err(TxErr::MissingTx);
+      return Err(TxErrKind::MissingTx);
     }
 
-    if let Some(account) = self.accounts.get_mut(&client) {
-      account.resolve(tx)
-    } else {
-      Err(TxErr::AccessUnavailable)
+    if let Some(transfer) = self.transfer_of(id, client)? {
+      let destination = transfer.destination();
+      return self
+        .unlocked_account_mut(destination)?
+        .resolve(Resolve::new(id, destination));
     }
+
+    self.unlocked_account_mut(client)?.resolve(tx)
   }
 
-  pub(crate) fn chargeback(&mut self, id: TxId, client: ClientId) -> TxResult {
+  /// Charge back a disputed transaction, returning the client that paid the fee and the
+  /// fee.
+  pub(crate) fn chargeback(
+    &mut self,
+    id: TxId,
+    client: ClientId,
+  ) -> Result<(ClientId, Decimal), TxErrKind> {
     let tx = Chargeback::new(id, client);
 
     if !self.tx_ids.contains(&id) {
-      return Err(TxErr::MissingTx);
+      return Err(TxErrKind::MissingTx);
     }
 
-    let mut account = match self.accounts.remove(&client) {
-      Some(account) => account,
-      None => return Err(TxErr::AccessUnavailable),
+    let transfer = self.transfer_of(id, client)?;
+
+    // A chargeback of a transfer reverses the deposit into its destination, who pays the
+    // fee whichever party submitted the chargeback.
+    let payer = transfer.map_or(client, |transfer| transfer.destination());
+
+    // The fee is based on the disputed amount, and may make the available funds negative.
+    let fee = match self.accounts.get(&payer).and_then(|account| account.disputed(id)) {
+      Some(disputed) => self.fee(TxType::Chargeback, payer, disputed)?,
+      None => Decimal::ZERO,
     };
 
-    if let Err(err) = account.chargeback(tx) {
-      self.accounts.insert(client, account);
-      return Err(err);
+    self.check_fee(payer, fee)?;
+
+    // The source of a transfer is credited back with the amount charged back to the
+    // destination. Both are checked first, so that the chargeback is applied to both or
+    // neither, and only the destination is locked.
+    if let Some(transfer) = transfer {
+      let (source, destination) = (transfer.source(), transfer.destination());
+      let refund = self
+        .accounts
+        .get(&destination)
+        .and_then(|account| account.disputed(id))
+        .ok_or(TxErrKind::MissingTxForClient)?;
+
+      self.unlocked_account_mut(source)?.check_refund(id, refund)?;
+      self
+        .unlocked_account_mut(destination)?
+        .chargeback(Chargeback::new(id, destination))
+        .expect("Destination was checked to have a disputed deposit");
+      self.unlocked_account_mut(source)?.refund(id, refund).expect("Refund was checked");
+      self.collect_fee(payer, fee);
+      self.lock(destination);
+      return Ok((payer, fee));
     }
 
-    self.accounts_locked.insert(client, account.lock());
+    self.unlocked_account_mut(client)?.chargeback(tx)?;
+    self.collect_fee(client, fee);
+    self.lock(client);
 
-    Ok(())
+    Ok((client, fee))
   }
-}
-
-#[cfg(test)]
-mod db_tests {
-  use crate::{Db, Tx, TxErr};
-  use rust_decimal::Decimal;
 
-  #[test]
-  fn valid_transactions() {
-    let mut db = Db::new();
-    assert_eq!(db.process(&Tx::new_deposit(5, 1, Decimal::from(5))), Ok(()));
-    assert_eq!(db.process(&Tx::new_deposit(4, 1, Decimal::from(5))), Ok(()));
-    assert_eq!(db.process(&Tx::new_deposit(3, 2, Decimal::from(5))), Ok(()));
-    assert_eq!(db.process(&Tx::new_withdraw(2, 1, Decimal::from(10))), Ok(()));
-    assert_eq!(db.process(&Tx::new_withdraw(1, 2, Decimal::from(5))), Ok(()));
+  /// Move an account that was just checked to be unlocked into its locked state.
+  fn lock(&mut self, client: ClientId) {
+    match self.accounts.remove(&client) {
+      Some(AnyAccount::Unlocked(account)) => {
+        self.accounts.insert(client, account.lock().into());
+      }
+      _ => unreachable!("Attempt to lock a locked or missing account"),
+    }
   }
 
-  #[test]
-  fn duplicate_tx_id() {
-    let mut db = Db::new();
-    assert_eq!(db.process(&Tx::new_deposit(4, 1, Decimal::from(5))), Ok(()));
-    assert_eq!(
-      db.process(&Tx::new_withdraw(4, 1, Decimal::from(5))),
-      Err(TxErr::Duplicate)
-    );
+  /// Get the transfer with id *id*, if there is one, checking that *client* is one of its
+  /// parties and that neither of their accounts is locked.
+  fn transfer_of(
+    &self,
+    id: TxId,
+    client: ClientId,
+  ) -> Result<Option<Transfer>, TxErrKind> {
+    let transfer = match self.transfers.get(&id) {
+      Some(transfer) => *transfer,
+      None => return Ok(None),
+    };
+
+    if transfer.counterparty(client).is_none() {
+      return Err(TxErrKind::MissingTxForClient);
+    }
+
+    for party in [transfer.source(), transfer.destination()] {
+      match self.accounts.get(&party) {
+        Some(AnyAccount::Unlocked(_)) => {}
+        Some(AnyAccount::Locked(_)) => return Err(TxErrKind::AccountLocked),
+        None => return Err(TxErrKind::AccessUnavailable),
+      }
+    }
+
+    Ok(Some(transfer))
+  }
+
+  fn transfer(
+    &mut self,
+    id: TxId,
+    source: ClientId,
+    destination: ClientId,
+    amount: Decimal,
+    timestamp: Option<u64>,
+  ) -> Result<(), TxErrKind> {
+    let tx = Transfer::new(id, source, destination, amount)?;
+
+    if self.tx_ids.contains(&id) {
+      return Err(TxErrKind::Duplicate);
+    }
+
+    // Check everything that could make the deposit into the destination fail before
+    // withdrawing from the source, so that the transfer is applied in full or not at all.
+    match self.accounts.get(&destination) {
+      Some(AnyAccount::Unlocked(account))
+        if account.total().checked_add(amount).is_none()
+          || account.available().checked_add(amount).is_none() =>
+      {
+        return Err(TxErrKind::Overflow)
+      }
+      Some(AnyAccount::Locked(_)) => return Err(TxErrKind::AccountLocked),
+      _ => {}
+    }
+
+    // The source is subject to the same velocity limits as for a withdrawal.
+    self.unlocked_account_mut(source)?.check_funds(amount)?;
+    self.check_velocity(source, amount, timestamp)?;
+    self.unlocked_account_mut(source)?.withdraw(Withdraw::new(id, source, amount)?)?;
+    self.count_velocity(source, amount, timestamp);
+
+    let deposit = Deposit::new(id, destination, amount)?;
+    let account = self
+      .accounts
+      .entry(destination)
+      .or_insert_with(|| AnyAccount::from(Account::new(destination)));
+
+    match account {
+      AnyAccount::Unlocked(account) => {
+        account.deposit(deposit).expect("Destination account was checked for overflows")
+      }
+      AnyAccount::Locked(_) => {
+        unreachable!("Destination account was checked to be unlocked")
+      }
+    }
+
+    self.tx_ids.insert(id);
+    self.transfers.insert(id, tx);
+
+    Ok(())
+  }
+
+  fn authorize(
+    &mut self,
+    id: TxId,
+    client: ClientId,
+    amount: Decimal,
+    timestamp: Option<u64>,
+  ) -> Result<(), TxErrKind> {
+    let expires = match (timestamp, self.authorization_ttl) {
+      (Some(timestamp), Some(ttl)) => Some(timestamp.saturating_add(ttl)),
+      _ => None,
+    };
+
+    let tx = Authorization::new(id, client, amount, expires)?;
+
+    if self.tx_ids.contains(&id) {
+      return Err(TxErrKind::Duplicate);
+    }
+
+    // The capture will stand as a withdrawal, so the client must be able to pay for its
+    // fee and take the authorized amount out within the velocity limits already.
+    let fee = self.fee(TxType::Withdrawal, client, amount)?;
+    let cost = amount.checked_add(fee).ok_or(TxErrKind::Overflow)?;
+    self.unlocked_account_mut(client)?.check_funds(cost)?;
+    self.check_velocity(client, amount, timestamp)?;
+
+    self.unlocked_account_mut(client)?.authorize(tx)?;
+    self.tx_ids.insert(id);
+
+    if let Some(expires) = expires {
+      self.next_expiry = Some(self.next_expiry.map_or(expires, |next| next.min(expires)));
+    }
+
+    Ok(())
+  }
+
+  fn capture(
+    &mut self,
+    id: TxId,
+    client: ClientId,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+  ) -> Result<Decimal, TxErrKind> {
+    let tx = Capture::new(id, client, amount, timestamp)?;
+
+    if !self.tx_ids.contains(&id) {
+      return Err(TxErrKind::MissingTx);
+    }
+
+    // A capture stands as a withdrawal of the captured amount, so it pays the same fee
+    // and counts towards the same velocity limits.
+    let account = self.unlocked_account_mut(client)?;
+    let captured = account.check_capture(&tx)?;
+    let authorized = account.authorization(id).expect("Capture was checked").amount();
+    let fee = self.fee(TxType::Withdrawal, client, captured)?;
+
+    // The fee is paid out of the available funds, including the released remainder.
+    if !fee.is_zero() {
+      let released = authorized - captured;
+      self.unlocked_account_mut(client)?.check_funds(fee - released)?;
+    }
+
+    self.check_velocity(client, captured, timestamp)?;
+    self.check_fee(client, fee)?;
+    self.unlocked_account_mut(client)?.capture(tx)?;
+    self.collect_fee(client, fee);
+    self.count_velocity(client, captured, timestamp);
+
+    Ok(fee)
+  }
+
+  fn void(&mut self, id: TxId, client: ClientId) -> Result<(), TxErrKind> {
+    let tx = Void::new(id, client);
+
+    if !self.tx_ids.contains(&id) {
+      return Err(TxErrKind::MissingTx);
+    }
+
+    self.unlocked_account_mut(client)?.void(tx)
+  }
+
+  fn limit(
+    &mut self,
+    id: TxId,
+    client: ClientId,
+    limit: Decimal,
+    operator: &str,
+    reason: &str,
+  ) -> Result<(), TxErrKind> {
+    if operator.is_empty() || reason.is_empty() {
+      return Err(TxErrKind::MissingAdminDetails);
+    }
+
+    if self.tx_ids.contains(&id) {
+      return Err(TxErrKind::Duplicate);
+    }
+
+    self.credit_limit(client, limit)?;
+    self.tx_ids.insert(id);
+
+    Ok(())
+  }
+
+  /// Set the credit limit of a locked or unlocked account, creating it if needed.
+  fn credit_limit(&mut self, client: ClientId, limit: Decimal) -> Result<(), TxErrKind> {
+    if limit.is_sign_negative() {
+      return Err(TxErrKind::NegativeAmount);
+    }
+
+    self
+      .accounts
+      .entry(client)
+      .or_insert_with(|| AnyAccount::from(Account::new(client)))
+      .set_credit_limit(limit);
+
+    Ok(())
+  }
+
+  fn release(
+    &mut self,
+    id: TxId,
+    client: ClientId,
+    amount: Option<Decimal>,
+    operator: String,
+    reason: String,
+  ) -> Result<(), TxErrKind> {
+    let tx = Release::new(id, client, amount, operator, reason)?;
+
+    if self.tx_ids.contains(&id) {
+      return Err(TxErrKind::Duplicate);
+    }
+
+    self.accounts.get_mut(&client).ok_or(TxErrKind::AccessUnavailable)?.release(tx)?;
+    self.tx_ids.insert(id);
+
+    Ok(())
+  }
+
+  fn unlock(
+    &mut self,
+    id: TxId,
+    client: ClientId,
+    operator: String,
+    reason: String,
+  ) -> Result<(), TxErrKind> {
+    let tx = Unlock::new(id, client, operator, reason)?;
+
+    if self.tx_ids.contains(&id) {
+      return Err(TxErrKind::Duplicate);
+    }
+
+    match self.accounts.remove(&client) {
+      Some(AnyAccount::Locked(account)) => {
+        self.accounts.insert(client, account.unlock(tx).into());
+      }
+      Some(account) => {
+        self.accounts.insert(client, account);
+        return Err(TxErrKind::AccountNotLocked);
+      }
+      None => return Err(TxErrKind::AccessUnavailable),
+    }
+
+    self.tx_ids.insert(id);
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod db_tests {
+  use crate::{
+    AnyAccount, ClientId, CreditLimit, Db, EntryKind, FeeRule, FeeSchedule, Observer,
+    OpeningBalance, Outcome, PrecisionPolicy, Rounding, Snapshot, Transaction,
+    Transition, Tx, TxErr, TxErrKind, TxId, TxState, TxType, VelocityLimits,
+    VelocityRule,
+  };
+  use rust_decimal::Decimal;
+  use std::cell::RefCell;
+  use std::rc::Rc;
+  use std::str::FromStr;
+
+  fn process(db: &mut Db, tx: &Tx) -> Result<(), TxErrKind> {
+    db.process(tx).map(|_| ()).map_err(|err| err.kind())
+  }
+
+  fn dec(value: &str) -> Decimal {
+    Decimal::from_str(value).unwrap()
+  }
+
+  /// A rule charging a *fixed* fee plus *percent* of the amount of transactions of type
+  /// *typ*, with no minimum or maximum.
+  fn fee_rule(typ: TxType, fixed: &str, percent: &str) -> FeeRule {
+    FeeRule { typ, fixed: dec(fixed), percent: dec(percent), min: None, max: None }
+  }
+
+  /// A fee schedule with *rules*, whose fees go to client 9.
+  fn fee_schedule(rules: &[FeeRule]) -> FeeSchedule {
+    let mut fees = FeeSchedule::new(ClientId::new(9));
+
+    for rule in rules {
+      fees.add_rule(rule).unwrap();
+    }
+
+    fees
+  }
+
+  /// Velocity limits with a default rule of *daily_amount* and *hourly_count*.
+  fn velocity(daily_amount: Option<&str>, hourly_count: Option<u32>) -> VelocityLimits {
+    let rule =
+      VelocityRule { client: None, daily_amount: daily_amount.map(dec), hourly_count };
+    let mut limits = VelocityLimits::new();
+    limits.add_rule(&rule).unwrap();
+    limits
+  }
+
+  /// The available, held and authorized funds, credit used and lock of *client*.
+  fn balances(db: &Db, client: u16) -> (Decimal, Decimal, Decimal, Decimal, bool) {
+    let account = db.get_account(ClientId::new(client)).unwrap();
+    let funds = (account.available(), account.held(), account.authorized());
+    (funds.0, funds.1, funds.2, account.credit_used(), account.is_locked())
+  }
+
+  #[test]
+  fn valid_transactions() {
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(5, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(4, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(3, 2, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_withdraw(2, 1, Decimal::from(10))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_withdraw(1, 2, Decimal::from(5))), Ok(()));
+  }
+
+  #[test]
+  fn duplicate_tx_id() {
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(4, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(4, 1, Decimal::from(5))),
+      Err(TxErrKind::Duplicate)
+    );
   }
 
   #[test]
   fn invalid_withdraw() {
     let mut db = Db::new();
-    assert_eq!(db.process(&Tx::new_deposit(5, 1, Decimal::from(5))), Ok(()));
-    assert_eq!(db.process(&Tx::new_deposit(4, 1, Decimal::from(5))), Ok(()));
-    assert_eq!(db.process(&Tx::new_deposit(3, 2, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(5, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(4, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(3, 2, Decimal::from(5))), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(2, 1, Decimal::from(15))),
+      Err(TxErrKind::Insufficient)
+    );
+    assert_eq!(process(&mut db, &Tx::new_withdraw(1, 2, Decimal::from(5))), Ok(()));
+  }
+
+  #[test]
+  fn partial_disputes() {
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(10))), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_partial_dispute(1, 1, Decimal::from(3))),
+      Ok(())
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_partial_dispute(1, 1, Decimal::from(4))),
+      Ok(())
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_partial_dispute(1, 1, Decimal::from(4))),
+      Err(TxErrKind::InvalidDisputeAmount)
+    );
+
+    let account = db.get_account(ClientId::new(1)).unwrap();
+    assert_eq!(account.available(), Decimal::from(3));
+    assert_eq!(account.held(), Decimal::from(7));
+
+    // A dispute without an amount covers whatever is left of the deposit.
+    assert_eq!(process(&mut db, &Tx::new_dispute(1, 1)), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_dispute(1, 1)),
+      Err(TxErrKind::InvalidDisputeAmount)
+    );
+    assert_eq!(process(&mut db, &Tx::new_resolve(1, 1)), Ok(()));
+
+    let account = db.get_account(ClientId::new(1)).unwrap();
+    assert_eq!(account.available(), Decimal::from(10));
+    assert_eq!(account.held(), Decimal::from(0));
+  }
+
+  #[test]
+  fn locked_account() {
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(2, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_dispute(1, 1)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_chargeback(1, 1)), Ok(()));
+
+    assert_eq!(
+      process(&mut db, &Tx::new_deposit(3, 1, Decimal::from(5))),
+      Err(TxErrKind::AccountLocked)
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(4, 1, Decimal::from(5))),
+      Err(TxErrKind::AccountLocked)
+    );
+    assert_eq!(process(&mut db, &Tx::new_dispute(2, 1)), Err(TxErrKind::AccountLocked));
+
+    assert_eq!(db.accounts().count(), 1);
+    let account = db.get_account(ClientId::new(1)).unwrap();
+    assert!(account.is_locked());
+    assert_eq!(account.available(), Decimal::from(5));
+  }
+
+  #[test]
+  fn unlock() {
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(2, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_unlock(3, 1, "risk", "review")),
+      Err(TxErrKind::AccountNotLocked)
+    );
+    assert_eq!(process(&mut db, &Tx::new_dispute(1, 1)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_chargeback(1, 1)), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_unlock(3, 1, "risk", "")),
+      Err(TxErrKind::MissingAdminDetails)
+    );
+    assert_eq!(process(&mut db, &Tx::new_unlock(3, 1, "risk", "review")), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(3, 1, Decimal::from(5))),
+      Err(TxErrKind::Duplicate)
+    );
+    assert_eq!(process(&mut db, &Tx::new_withdraw(4, 1, Decimal::from(5))), Ok(()));
+
+    match db.get_account(ClientId::new(1)).unwrap() {
+      AnyAccount::Unlocked(account) => {
+        assert_eq!(account.available(), Decimal::from(0));
+        assert_eq!(account.unlocks().len(), 1);
+        assert_eq!(account.unlocks()[0].operator(), "risk");
+        assert_eq!(account.unlocks()[0].reason(), "review");
+      }
+      AnyAccount::Locked(_) => panic!("Account should be unlocked"),
+    }
+  }
+
+  #[test]
+  fn chain_skips_failed_transactions() {
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(5))), Ok(()));
+    let head = db.chain().head();
+
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(2, 1, Decimal::from(10))),
+      Err(TxErrKind::Insufficient)
+    );
+    assert_eq!(db.chain().head(), head);
+    assert_eq!(db.chain().len(), 1);
+
+    assert_eq!(process(&mut db, &Tx::new_withdraw(2, 1, Decimal::from(5))), Ok(()));
+    assert_ne!(db.chain().head(), head);
+    assert_eq!(db.chain().len(), 2);
+  }
+
+  #[test]
+  fn journal() {
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(db.get_account(ClientId::new(1)).unwrap().journal(), None);
+
+    let mut db = Db::new().with_journal(true);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_withdraw(2, 1, Decimal::from(2))), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_partial_dispute(1, 1, Decimal::from(3))),
+      Ok(())
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(3, 1, Decimal::from(2))),
+      Err(TxErrKind::Insufficient)
+    );
+
+    let journal = db.get_account(ClientId::new(1)).unwrap().journal().unwrap();
+    assert_eq!(journal.len(), 3);
+    assert_eq!(journal[1].tx(), TxId::new(2));
+    assert_eq!(journal[1].available_delta(), Decimal::from(-2));
+    assert_eq!(journal[1].available(), Decimal::from(3));
+    assert_eq!(journal[2].typ(), TxType::Dispute);
+    assert_eq!(journal[2].available_delta(), Decimal::from(-3));
+    assert_eq!(journal[2].held_delta(), Decimal::from(3));
+    assert_eq!(journal[2].available(), Decimal::from(0));
+    assert_eq!(journal[2].held(), Decimal::from(3));
+  }
+
+  #[test]
+  fn error_context() {
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(5))), Ok(()));
+
+    let err = db.process(&Tx::new_withdraw(2, 1, Decimal::from(7))).unwrap_err();
+    assert_eq!(err.kind(), TxErrKind::Insufficient);
+    assert_eq!(err.tx(), Some(TxId::new(2)));
+    assert_eq!(err.client(), Some(ClientId::new(1)));
+    assert_eq!(err.amount(), Some(Decimal::from(7)));
+    assert_eq!(err.available(), Some(Decimal::from(5)));
+    assert_eq!(err.held(), Some(Decimal::from(0)));
+
+    let err = db.process(&Tx::new_withdraw(3, 2, Decimal::from(7))).unwrap_err();
+    assert_eq!(err.kind(), TxErrKind::AccessUnavailable);
+    assert_eq!(err.available(), None);
+  }
+
+  #[test]
+  fn typed_transactions() {
+    let mut db = Db::new();
+    let (tx, client) = (TxId::new(1), ClientId::new(1));
+
+    let deposit =
+      Transaction::Deposit { tx, client, amount: Decimal::from(5), timestamp: None };
+    assert!(db.apply(deposit).is_ok());
+    let dispute = Transaction::Dispute { tx, client, amount: None, timestamp: None };
+    assert!(db.apply(dispute).is_ok());
+    assert!(db.apply(Transaction::Resolve { tx, client, timestamp: None }).is_ok());
+    assert_eq!(db.chain().len(), 3);
+
+    let err = db.process(&Tx { amount: None, ..Tx::new_deposit(2, 1, Decimal::ZERO) });
+    assert_eq!(err.unwrap_err().kind(), TxErrKind::MissingAmount);
+    let err = db.process(&Tx { amount: Some(Decimal::ONE), ..Tx::new_resolve(1, 1) });
+    assert_eq!(err.unwrap_err().kind(), TxErrKind::ExtraneousAmount);
+    assert_eq!(db.chain().len(), 3);
+  }
+
+  #[derive(Default)]
+  struct Recorder {
+    applied: Vec<Outcome>,
+    expired: Vec<Outcome>,
+    rejected: Vec<TxErrKind>,
+  }
+
+  struct Shared(Rc<RefCell<Recorder>>);
+
+  impl Observer for Shared {
+    fn applied(&mut self, outcome: &Outcome) {
+      self.0.borrow_mut().applied.push(outcome.clone());
+    }
+
+    fn expired(&mut self, outcome: &Outcome) {
+      self.0.borrow_mut().expired.push(outcome.clone());
+    }
+
+    fn rejected(&mut self, err: &TxErr) {
+      self.0.borrow_mut().rejected.push(err.kind());
+    }
+  }
+
+  #[test]
+  fn outcomes() {
+    let recorder = Rc::new(RefCell::new(Recorder::default()));
+    let mut db = Db::new().with_observer(Box::new(Shared(Rc::clone(&recorder))));
+    let (five, zero) = (Decimal::from(5), Decimal::ZERO);
+
+    let outcome = db.process(&Tx::new_deposit(1, 1, five)).unwrap();
+    assert!(outcome.created());
+    assert_eq!(outcome.before(), (zero, zero, zero));
+    assert_eq!(outcome.after(), (five, zero, zero));
+    assert_eq!(
+      outcome.transition(),
+      Some(Transition { from: None, to: TxState::Released })
+    );
+    assert_eq!(outcome.effects().len(), 1);
+
+    let outcome = db.process(&Tx::new_dispute(1, 1)).unwrap();
+    assert!(!outcome.created());
+    assert_eq!(outcome.after(), (zero, five, zero));
+    let transition = Transition { from: Some(TxState::Released), to: TxState::Held };
+    assert_eq!(outcome.transition(), Some(transition));
+
+    let outcome = db.process(&Tx::new_chargeback(1, 1)).unwrap();
+    assert_eq!(outcome.after(), (zero, zero, zero));
+    let transition = Transition { from: Some(TxState::Held), to: TxState::Reversed };
+    assert_eq!(outcome.transition(), Some(transition));
+    assert!(outcome.locked());
+
+    assert!(db.process(&Tx::new_deposit(2, 1, five)).is_err());
+
+    let outcome = db.process(&Tx::new_unlock(3, 1, "risk", "review")).unwrap();
+    assert_eq!(outcome.transition(), None);
+    assert!(outcome.unlocked());
+
+    let recorder = recorder.borrow();
+    assert_eq!(recorder.applied.len(), 4);
+    assert_eq!(recorder.rejected, vec![TxErrKind::AccountLocked]);
+  }
+
+  #[test]
+  fn outcome_effects() {
+    let (zero, five, ten) = (Decimal::ZERO, Decimal::from(5), Decimal::from(10));
+
+    // A transfer affects both of its parties.
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, ten)), Ok(()));
+    let outcome = db.process(&Tx::new_transfer(2, 1, 2, five)).unwrap();
+    assert_eq!(outcome.effects().len(), 2);
+    assert_eq!(outcome.after(), (five, zero, zero));
+    let destination = outcome.effect(ClientId::new(2)).unwrap();
+    assert!(destination.created());
+    assert_eq!(destination.before(), (zero, zero, zero));
+    assert_eq!(destination.after(), (five, zero, zero));
+
+    // A chargeback of the transfer credits the source and locks the destination, whoever
+    // submits it.
+    assert_eq!(process(&mut db, &Tx::new_dispute(2, 1)), Ok(()));
+    let outcome = db.process(&Tx::new_chargeback(2, 1)).unwrap();
+    let (source, destination) = (&outcome.effects()[0], &outcome.effects()[1]);
+    assert_eq!(source.client(), ClientId::new(1));
+    assert_eq!(source.after(), (ten, zero, zero));
+    assert!(!source.locked());
+    assert_eq!(destination.before(), (zero, five, zero));
+    assert_eq!(destination.after(), (zero, zero, zero));
+    assert!(destination.locked());
+    let transition = Transition { from: Some(TxState::Held), to: TxState::Reversed };
+    assert_eq!(destination.transition(), Some(transition));
+
+    // A fee credits the fee account.
+    let fees = fee_schedule(&[fee_rule(TxType::Withdrawal, "1", "0")]);
+    let mut db = Db::new().with_fee_schedule(fees).with_authorization_ttl(60);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, ten)), Ok(()));
+    let outcome = db.process(&Tx::new_withdraw(2, 1, Decimal::from(4))).unwrap();
+    assert_eq!(outcome.after(), (five, zero, zero));
+    let fee_account = outcome.effect(ClientId::new(9)).unwrap();
+    assert!(fee_account.created());
+    assert_eq!(fee_account.after(), (Decimal::ONE, zero, zero));
+    assert_eq!(fee_account.transition(), None);
+
+    // An authorization moves funds to the authorized balance.
+    let recorder = Rc::new(RefCell::new(Recorder::default()));
+    let mut db = db.with_observer(Box::new(Shared(Rc::clone(&recorder))));
+    let authorize = Tx::new_authorize(3, 1, Decimal::from(3)).with_timestamp(0);
+    let outcome = db.process(&authorize).unwrap();
+    assert_eq!(outcome.after(), (Decimal::from(2), zero, Decimal::from(3)));
+    assert_eq!(outcome.effects().len(), 1);
+
+    // Its expiry is reported before the transaction that reached it.
+    let deposit = Tx::new_deposit(4, 2, five).with_timestamp(60);
+    assert_eq!(process(&mut db, &deposit), Ok(()));
+    let recorder = recorder.borrow();
+    assert_eq!(recorder.expired.len(), 1);
+    let expiry = &recorder.expired[0];
+    assert_eq!((expiry.tx(), expiry.typ()), (TxId::new(3), TxType::Authorize));
+    assert_eq!(expiry.client(), ClientId::new(1));
+    assert_eq!(expiry.before(), (Decimal::from(2), zero, Decimal::from(3)));
+    assert_eq!(expiry.after(), (five, zero, zero));
+    assert_eq!(recorder.applied.last().unwrap().tx(), TxId::new(4));
+  }
+
+  #[test]
+  fn precision_policy() {
+    let amount = |value: &str| value.parse::<Decimal>().unwrap();
+
+    let mut db = Db::new();
+    assert_eq!(
+      process(&mut db, &Tx::new_deposit(1, 1, amount("1.00005"))),
+      Err(TxErrKind::ExcessivePrecision)
+    );
+    assert_eq!(process(&mut db, &Tx::new_deposit(2, 1, amount("1.00000"))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(3, 1, Decimal::ZERO)), Ok(()));
+
+    let policy = PrecisionPolicy::Round(Rounding::HalfUp);
+    let mut db = Db::new().with_precision_policy(policy).with_reject_zero_amounts(true);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, amount("1.00005"))), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_partial_dispute(1, 1, amount("0.50005"))),
+      Ok(())
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(2, 1, amount("0.00004"))),
+      Err(TxErrKind::ZeroAmount)
+    );
+
+    let account = db.get_account(ClientId::new(1)).unwrap();
+    assert_eq!(account.available(), amount("0.5"));
+    assert_eq!(account.held(), amount("0.5001"));
+  }
+
+  #[test]
+  fn transfers() {
+    let (zero, two, three, five) =
+      (Decimal::ZERO, Decimal::from(2), Decimal::from(3), Decimal::from(5));
+
+    let mut db = Db::new().with_journal(true);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, five)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_transfer(2, 1, 2, three)), Ok(()));
+    assert_eq!(balances(&db, 1), (two, zero, zero, zero, false));
+    assert_eq!(balances(&db, 2), (three, zero, zero, zero, false));
+    assert_eq!(db.get_account(ClientId::new(2)).unwrap().journal().unwrap().len(), 1);
+
+    assert_eq!(
+      process(&mut db, &Tx::new_transfer(3, 1, 2, three)),
+      Err(TxErrKind::Insufficient)
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_transfer(3, 1, 1, two)),
+      Err(TxErrKind::SelfTransfer)
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_transfer(2, 1, 2, two)),
+      Err(TxErrKind::Duplicate)
+    );
+    assert_eq!(
+      process(&mut db, &Tx { destination: None, ..Tx::new_transfer(3, 1, 2, two) }),
+      Err(TxErrKind::MissingDestination)
+    );
+    assert_eq!(balances(&db, 1), (two, zero, zero, zero, false));
+    assert_eq!(balances(&db, 2), (three, zero, zero, zero, false));
+  }
+
+  #[test]
+  fn transfer_disputes() {
+    let (zero, two, three, five) =
+      (Decimal::ZERO, Decimal::from(2), Decimal::from(3), Decimal::from(5));
+
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, five)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_transfer(2, 1, 2, three)), Ok(()));
+
+    // A dispute holds the funds in the destination only, whichever party refers to the
+    // transfer, so the transfer is not counted twice.
+    assert_eq!(
+      process(&mut db, &Tx::new_dispute(2, 3)),
+      Err(TxErrKind::MissingTxForClient)
+    );
+    assert_eq!(process(&mut db, &Tx::new_dispute(2, 1)), Ok(()));
+    assert_eq!(balances(&db, 1), (two, zero, zero, zero, false));
+    assert_eq!(balances(&db, 2), (zero, three, zero, zero, false));
+    assert_eq!(process(&mut db, &Tx::new_resolve(2, 1)), Ok(()));
+    assert_eq!(balances(&db, 1), (two, zero, zero, zero, false));
+    assert_eq!(balances(&db, 2), (three, zero, zero, zero, false));
+
+    let restored = Db::restore(db.snapshot()).unwrap();
+    assert_eq!(restored.snapshot().transfers, db.snapshot().transfers);
+  }
+
+  #[test]
+  fn transfer_chargebacks() {
+    let (zero, three, five) = (Decimal::ZERO, Decimal::from(3), Decimal::from(5));
+
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, five)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_transfer(2, 1, 2, three)), Ok(()));
+
+    // A chargeback credits the source back and locks the destination only.
+    assert_eq!(process(&mut db, &Tx::new_dispute(2, 2)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_chargeback(2, 2)), Ok(()));
+    assert_eq!(balances(&db, 1), (five, zero, zero, zero, false));
+    assert_eq!(balances(&db, 2), (zero, zero, zero, zero, true));
+    assert_eq!(process(&mut db, &Tx::new_dispute(2, 1)), Err(TxErrKind::AccountLocked));
+
+    assert_eq!(
+      process(&mut db, &Tx::new_transfer(4, 1, 2, zero)),
+      Err(TxErrKind::AccountLocked)
+    );
+  }
+
+  #[test]
+  fn transfer_chargeback_failure() {
+    let (zero, five, ten) = (Decimal::ZERO, Decimal::from(5), Decimal::from(10));
+
+    // A chargeback that fails for either party applies to neither.
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, ten)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_transfer(2, 1, 2, ten)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(3, 1, Decimal::MAX - five)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_dispute(2, 2)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_chargeback(2, 2)), Err(TxErrKind::Overflow));
+    assert_eq!(balances(&db, 1), (Decimal::MAX - five, zero, zero, zero, false));
+    assert_eq!(balances(&db, 2), (zero, ten, zero, zero, false));
+    assert_eq!(process(&mut db, &Tx::new_resolve(2, 1)), Ok(()));
+    assert_eq!(balances(&db, 2), (ten, zero, zero, zero, false));
+  }
+
+  #[test]
+  fn credit_limits() {
+    let (zero, five, ten) = (Decimal::ZERO, Decimal::from(5), Decimal::from(10));
+
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, ten)), Ok(()));
+    assert!(db.set_credit_limit(&CreditLimit { client: 1, limit: ten }).is_ok());
+    assert_eq!(process(&mut db, &Tx::new_withdraw(2, 1, Decimal::from(15))), Ok(()));
+    assert_eq!(balances(&db, 1), (-five, zero, zero, five, false));
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(3, 1, ten)),
+      Err(TxErrKind::CreditLimitExceeded)
+    );
+
+    // Raise the limit with a transaction.
+    assert_eq!(
+      process(&mut db, &Tx::new_limit(4, 1, Decimal::from(20), "alice", "raised")),
+      Ok(())
+    );
+    assert_eq!(process(&mut db, &Tx::new_withdraw(5, 1, ten)), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("-15"), zero, zero, dec("15"), false));
+    assert_eq!(db.get_account(ClientId::new(1)).unwrap().credit_limit(), dec("20"));
+
+    // Lowering the limit only prevents further withdrawals.
+    assert_eq!(process(&mut db, &Tx::new_limit(6, 1, zero, "alice", "lowered")), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("-15"), zero, zero, dec("15"), false));
+    assert_eq!(db.get_account(ClientId::new(1)).unwrap().credit_limit(), zero);
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(7, 1, Decimal::ONE)),
+      Err(TxErrKind::Insufficient)
+    );
+    assert_eq!(process(&mut db, &Tx::new_deposit(8, 1, Decimal::from(20))), Ok(()));
+    assert_eq!(balances(&db, 1), (five, zero, zero, zero, false));
+
+    // A limit creates the account.
+    assert_eq!(
+      process(&mut db, &Tx::new_limit(9, 2, five, "alice", "new client")),
+      Ok(())
+    );
+    assert_eq!(process(&mut db, &Tx::new_transfer(10, 2, 1, five)), Ok(()));
+    assert_eq!(balances(&db, 2), (-five, zero, zero, five, false));
+    assert_eq!(db.get_account(ClientId::new(2)).unwrap().credit_limit(), five);
+
+    assert_eq!(
+      process(&mut db, &Tx::new_limit(11, 3, -five, "alice", "new client")),
+      Err(TxErrKind::NegativeAmount)
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_limit(10, 3, five, "alice", "new client")),
+      Err(TxErrKind::Duplicate)
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_limit(11, 3, five, "", "new client")),
+      Err(TxErrKind::MissingAdminDetails)
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_limit(11, 3, five, "alice", "")),
+      Err(TxErrKind::MissingAdminDetails)
+    );
+    assert!(db.get_account(ClientId::new(3)).is_none());
+    assert_eq!(
+      db.set_credit_limit(&CreditLimit { client: 3, limit: -five }).map_err(|e| e.kind()),
+      Err(TxErrKind::NegativeAmount)
+    );
+
+    // Credit limits survive a snapshot.
+    let db = Db::restore(db.snapshot()).unwrap();
+    assert_eq!(balances(&db, 2), (-five, zero, zero, five, false));
+    assert_eq!(db.get_account(ClientId::new(2)).unwrap().credit_limit(), five);
+  }
+
+  #[test]
+  fn velocity_limits() {
+    let withdraw = |tx, amount: i64, timestamp| {
+      Tx::new_withdraw(tx, 1, Decimal::from(amount)).with_timestamp(timestamp)
+    };
+
+    let mut db = Db::new().with_velocity_limits(velocity(Some("100"), Some(2)));
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(1000))), Ok(()));
+    assert_eq!(process(&mut db, &withdraw(2, 50, 0)), Ok(()));
+    assert_eq!(process(&mut db, &withdraw(3, 10, 60)), Ok(()));
+    // Too many withdrawals in an hour.
+    assert_eq!(process(&mut db, &withdraw(4, 10, 120)), Err(TxErrKind::VelocityExceeded));
+    assert_eq!(process(&mut db, &withdraw(5, 10, 3600)), Ok(()));
+    // Too much in a day.
+    assert_eq!(
+      process(&mut db, &withdraw(6, 31, 7200)),
+      Err(TxErrKind::VelocityExceeded)
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(7, 1, Decimal::ONE)),
+      Err(TxErrKind::MissingTimestamp)
+    );
+  }
+
+  #[test]
+  fn velocity_history() {
+    let limits = velocity(Some("100"), Some(2));
+    let withdraw = |tx, amount: i64, timestamp| {
+      Tx::new_withdraw(tx, 1, Decimal::from(amount)).with_timestamp(timestamp)
+    };
+
+    let mut db = Db::new().with_velocity_limits(limits.clone());
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(1000))), Ok(()));
+    assert_eq!(process(&mut db, &withdraw(2, 50, 0)), Ok(()));
+    assert_eq!(process(&mut db, &withdraw(3, 10, 60)), Ok(()));
+    assert_eq!(process(&mut db, &withdraw(4, 10, 3600)), Ok(()));
+
+    // Withdrawals that fail otherwise do not count, and the history survives a snapshot.
+    assert_eq!(process(&mut db, &withdraw(5, 5000, 7200)), Err(TxErrKind::Insufficient));
+    let mut db = Db::restore(db.snapshot()).unwrap().with_velocity_limits(limits);
+    assert_eq!(process(&mut db, &withdraw(6, 30, 7200)), Ok(()));
+    assert_eq!(
+      process(&mut db, &withdraw(7, 1, 10800)),
+      Err(TxErrKind::VelocityExceeded)
+    );
+    assert_eq!(process(&mut db, &withdraw(8, 40, 86400)), Ok(()));
+
+    let account = db.get_account(ClientId::new(1)).unwrap();
+    assert_eq!(account.available(), Decimal::from(860));
+  }
+
+  #[test]
+  fn velocity_transfers() {
+    // Transfers count towards the limits of their source, like withdrawals.
+    let transfer = |tx, amount: i64, timestamp| {
+      Tx::new_transfer(tx, 1, 2, Decimal::from(amount)).with_timestamp(timestamp)
+    };
+    let withdraw = |tx, amount: i64, timestamp| {
+      Tx::new_withdraw(tx, 1, Decimal::from(amount)).with_timestamp(timestamp)
+    };
+
+    let mut db = Db::new().with_velocity_limits(velocity(Some("100"), Some(2)));
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(1000))), Ok(()));
+    assert_eq!(process(&mut db, &transfer(2, 60, 0)), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_transfer(3, 1, 2, Decimal::ONE)),
+      Err(TxErrKind::MissingTimestamp)
+    );
+    assert_eq!(process(&mut db, &withdraw(4, 50, 60)), Err(TxErrKind::VelocityExceeded));
+    assert_eq!(process(&mut db, &transfer(5, 40, 120)), Ok(()));
+    assert_eq!(process(&mut db, &transfer(6, 1, 180)), Err(TxErrKind::VelocityExceeded));
+    assert_eq!(process(&mut db, &transfer(7, 5000, 86400)), Err(TxErrKind::Insufficient));
+
+    let account = db.get_account(ClientId::new(2)).unwrap();
+    assert_eq!(account.available(), Decimal::from(100));
+  }
+
+  #[test]
+  fn velocity_out_of_order() {
+    let withdraw = |tx, amount: i64, timestamp| {
+      Tx::new_withdraw(tx, 1, Decimal::from(amount)).with_timestamp(timestamp)
+    };
+
+    // Out-of-order withdrawals still see the history around them, however late the latest
+    // withdrawal is, up to a day behind it.
+    let hour = 3600;
+    let mut db = Db::new().with_velocity_limits(velocity(Some("100"), Some(2)));
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(1000))), Ok(()));
+    assert_eq!(process(&mut db, &withdraw(2, 60, 48 * hour)), Ok(()));
+    assert_eq!(process(&mut db, &withdraw(3, 30, 68 * hour)), Ok(()));
+    assert_eq!(process(&mut db, &withdraw(4, 5, 78 * hour)), Ok(()));
+    assert_eq!(
+      process(&mut db, &withdraw(5, 20, 56 * hour)),
+      Err(TxErrKind::VelocityExceeded)
+    );
+    assert_eq!(process(&mut db, &withdraw(6, 5, 56 * hour)), Ok(()));
+    assert_eq!(
+      process(&mut db, &withdraw(7, 1, 54 * hour)),
+      Err(TxErrKind::StaleTimestamp)
+    );
+  }
+
+  #[test]
+  fn fees() {
+    let zero = Decimal::ZERO;
+    let withdrawal =
+      FeeRule { max: Some(dec("2")), ..fee_rule(TxType::Withdrawal, "0.5", "1") };
+
+    let mut db = Db::new().with_fee_schedule(fee_schedule(&[withdrawal]));
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("300"))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_withdraw(2, 1, dec("100"))), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("198.5"), zero, zero, zero, false));
+    assert_eq!(balances(&db, 9), (dec("1.5"), zero, zero, zero, false));
+
+    // The fee is capped.
+    assert_eq!(process(&mut db, &Tx::new_withdraw(3, 1, dec("190"))), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("6.5"), zero, zero, zero, false));
+    assert_eq!(balances(&db, 9), (dec("3.5"), zero, zero, zero, false));
+
+    // The client cannot pay for the fee.
+    assert_eq!(
+      process(&mut db, &Tx::new_withdraw(4, 1, dec("6.5"))),
+      Err(TxErrKind::Insufficient)
+    );
+
+    // The fee account pays no fees.
+    assert_eq!(process(&mut db, &Tx::new_withdraw(5, 9, dec("3.5"))), Ok(()));
+    assert_eq!(balances(&db, 9), (zero, zero, zero, zero, false));
+  }
+
+  #[test]
+  fn fee_journal() {
+    let withdrawal = fee_rule(TxType::Withdrawal, "0.5", "1");
+    let mut db =
+      Db::new().with_fee_schedule(fee_schedule(&[withdrawal])).with_journal(true);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("300"))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_withdraw(2, 1, dec("100"))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_withdraw(3, 9, dec("1.5"))), Ok(()));
+
+    // The fee gets an entry of its own after the transaction's.
+    let journal = db.get_account(ClientId::new(1)).unwrap().journal().unwrap();
+    assert_eq!(journal.len(), 3);
+    assert_eq!(journal[1].tx(), TxId::new(2));
+    assert_eq!(journal[1].kind(), EntryKind::Transaction);
+    assert_eq!(journal[1].available_delta(), dec("-100"));
+    assert_eq!(journal[2].tx(), TxId::new(2));
+    assert_eq!(journal[2].typ(), TxType::Withdrawal);
+    assert_eq!(journal[2].kind(), EntryKind::Fee);
+    assert_eq!(journal[2].available_delta(), dec("-1.5"));
+    assert_eq!(journal[2].available(), dec("198.5"));
+
+    let journal = db.get_account(ClientId::new(9)).unwrap().journal().unwrap();
+    assert_eq!(journal.len(), 2);
+    assert_eq!(journal[0].tx(), TxId::new(2));
+    assert_eq!(journal[0].kind(), EntryKind::Fee);
+    assert_eq!(journal[0].available_delta(), dec("1.5"));
+    assert_eq!(journal[1].tx(), TxId::new(3));
+    assert_eq!(journal[1].kind(), EntryKind::Transaction);
+  }
+
+  #[test]
+  fn chargeback_fees() {
+    let zero = Decimal::ZERO;
+    let chargeback = fee_rule(TxType::Chargeback, "10", "0");
+    let mut db =
+      Db::new().with_fee_schedule(fee_schedule(&[chargeback])).with_journal(true);
+
+    // The chargeback fee may make the available funds negative.
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("5"))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(2, 1, dec("1"))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_dispute(2, 1)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_chargeback(2, 1)), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("-5"), zero, zero, dec("5"), true));
+    assert_eq!(balances(&db, 9), (dec("10"), zero, zero, zero, false));
+
+    let journal = db.get_account(ClientId::new(1)).unwrap().journal().unwrap();
+    assert_eq!(journal.len(), 5);
+    assert_eq!(journal[3].kind(), EntryKind::Transaction);
+    assert_eq!(journal[3].available(), dec("5"));
+    assert_eq!(journal[4].tx(), TxId::new(2));
+    assert_eq!(journal[4].kind(), EntryKind::Fee);
+    assert_eq!(journal[4].available(), dec("-5"));
+
+    let journal = db.get_account(ClientId::new(9)).unwrap().journal().unwrap();
+    assert_eq!(journal[0].typ(), TxType::Chargeback);
+    assert_eq!(journal[0].available(), dec("10"));
+  }
+
+  #[test]
+  fn transfer_chargeback_fees() {
+    let zero = Decimal::ZERO;
+    let fees = fee_schedule(&[fee_rule(TxType::Chargeback, "10", "0")]);
+
+    // The destination pays the fee, whichever party submits the chargeback.
+    for submitter in [1, 2] {
+      let mut db = Db::new().with_fee_schedule(fees.clone()).with_journal(true);
+      assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("50"))), Ok(()));
+      assert_eq!(process(&mut db, &Tx::new_transfer(2, 1, 2, dec("20"))), Ok(()));
+      assert_eq!(process(&mut db, &Tx::new_dispute(2, submitter)), Ok(()));
+      assert_eq!(process(&mut db, &Tx::new_chargeback(2, submitter)), Ok(()));
+      assert_eq!(balances(&db, 1), (dec("50"), zero, zero, zero, false));
+      assert_eq!(balances(&db, 2), (dec("-10"), zero, zero, dec("10"), true));
+      assert_eq!(balances(&db, 9), (dec("10"), zero, zero, zero, false));
+
+      let journal = db.get_account(ClientId::new(1)).unwrap().journal().unwrap();
+      assert_eq!(journal.last().unwrap().kind(), EntryKind::Transaction);
+      assert_eq!(journal.last().unwrap().available(), dec("50"));
+
+      let journal = db.get_account(ClientId::new(2)).unwrap().journal().unwrap();
+      let (entry, fee) = (&journal[journal.len() - 2], &journal[journal.len() - 1]);
+      assert_eq!(entry.kind(), EntryKind::Transaction);
+      assert_eq!(entry.held_delta(), dec("-20"));
+      assert_eq!(entry.available(), zero);
+      assert_eq!(fee.kind(), EntryKind::Fee);
+      assert_eq!(fee.available_delta(), dec("-10"));
+    }
+  }
+
+  #[test]
+  fn authorizations() {
+    let zero = Decimal::ZERO;
+
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("100"))), Ok(()));
+    let authorize = Tx::new_authorize(2, 1, dec("30"));
+    assert_eq!(process(&mut db, &authorize), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("70"), zero, dec("30"), zero, false));
+    assert_eq!(process(&mut db, &authorize), Err(TxErrKind::Duplicate));
+    assert_eq!(
+      process(&mut db, &Tx::new_authorize(3, 1, dec("80"))),
+      Err(TxErrKind::Insufficient)
+    );
+    assert_eq!(balances(&db, 1), (dec("70"), zero, dec("30"), zero, false));
+  }
+
+  #[test]
+  fn partial_captures() {
+    let zero = Decimal::ZERO;
+
+    let mut db = Db::new().with_journal(true);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("100"))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_authorize(2, 1, dec("30"))), Ok(()));
+
+    // A partial capture withdraws the captured amount and releases the rest.
+    assert_eq!(
+      process(&mut db, &Tx::new_partial_capture(2, 1, dec("40"))),
+      Err(TxErrKind::ExcessiveCapture)
+    );
+    let capture = Tx::new_partial_capture(2, 1, dec("20"));
+    assert_eq!(process(&mut db, &capture), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("80"), zero, zero, zero, false));
+    assert_eq!(process(&mut db, &capture), Err(TxErrKind::MissingTxForClient));
+
+    // The journal records the movements of the authorized funds.
+    let journal = db.get_account(ClientId::new(1)).unwrap().journal().unwrap();
+    assert_eq!(journal[1].typ(), TxType::Authorize);
+    assert_eq!(journal[1].available_delta(), dec("-30"));
+    assert_eq!(journal[1].authorized_delta(), dec("30"));
+    assert_eq!(journal[1].authorized(), dec("30"));
+    assert_eq!(journal[2].typ(), TxType::Capture);
+    assert_eq!(journal[2].available_delta(), dec("10"));
+    assert_eq!(journal[2].authorized_delta(), dec("-30"));
+    assert_eq!(journal[2].authorized(), zero);
+
+    // The capture can be disputed like a withdrawal.
+    let account = db.get_account(ClientId::new(1)).unwrap();
+    assert_eq!(account.tx_state(TxId::new(2)), Some(TxState::Released));
+    assert_eq!(process(&mut db, &Tx::new_dispute(2, 1)), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("80"), dec("20"), zero, zero, false));
+    assert_eq!(process(&mut db, &Tx::new_resolve(2, 1)), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("80"), zero, zero, zero, false));
+  }
+
+  #[test]
+  fn voids() {
+    let zero = Decimal::ZERO;
+
+    // A void releases the whole authorization.
+    let mut db = Db::new();
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("80"))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_authorize(2, 1, dec("10"))), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("70"), zero, dec("10"), zero, false));
+    assert_eq!(process(&mut db, &Tx::new_void(2, 1)), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("80"), zero, zero, zero, false));
+    assert_eq!(process(&mut db, &Tx::new_void(2, 1)), Err(TxErrKind::MissingTxForClient));
+    assert_eq!(process(&mut db, &Tx::new_void(3, 1)), Err(TxErrKind::MissingTx));
+  }
+
+  #[test]
+  fn authorization_expiry() {
+    let zero = Decimal::ZERO;
+
+    let mut db = Db::new().with_authorization_ttl(60).with_journal(true);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("80"))), Ok(()));
+
+    // Authorizations expire after the TTL, unless they have no timestamp.
+    let authorize = Tx::new_authorize(2, 1, dec("10")).with_timestamp(2000);
+    assert_eq!(process(&mut db, &authorize), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_authorize(3, 1, dec("5"))), Ok(()));
     assert_eq!(
-      db.process(&Tx::new_withdraw(2, 1, Decimal::from(15))),
-      Err(TxErr::Insufficient)
+      process(&mut db, &Tx::new_capture(2, 1).with_timestamp(2060)),
+      Err(TxErrKind::AuthorizationExpired)
     );
-    assert_eq!(db.process(&Tx::new_withdraw(1, 2, Decimal::from(5))), Ok(()));
+
+    // Expired authorizations are released once an applied transaction reaches expiry.
+    let withdraw =
+      |tx, timestamp| Tx::new_withdraw(tx, 1, dec("1")).with_timestamp(timestamp);
+    assert_eq!(process(&mut db, &withdraw(4, 2059)), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("64"), zero, dec("15"), zero, false));
+    assert_eq!(process(&mut db, &withdraw(5, 2060)), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("73"), zero, dec("5"), zero, false));
+    assert_eq!(db.clock(), Some(2060));
+
+    // The expiry is recorded before the transaction that reached it.
+    let account = db.get_account(ClientId::new(1)).unwrap();
+    let journal = account.journal().unwrap();
+    let (entry, last) = (&journal[journal.len() - 2], &journal[journal.len() - 1]);
+    assert_eq!(entry.kind(), EntryKind::Expiry);
+    assert_eq!(entry.tx(), TxId::new(2));
+    assert_eq!(entry.available_delta(), dec("10"));
+    assert_eq!(entry.authorized_delta(), dec("-10"));
+    assert_eq!(entry.authorized(), dec("5"));
+    assert_eq!(last.kind(), EntryKind::Transaction);
+    assert_eq!(last.tx(), TxId::new(5));
+
+    // Without a timestamp of their own, captures and voids are checked against the clock.
+    assert_eq!(
+      process(&mut db, &Tx::new_capture(2, 1)),
+      Err(TxErrKind::AuthorizationExpired)
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_void(2, 1)),
+      Err(TxErrKind::AuthorizationExpired)
+    );
+
+    // An authorization that is already expired by the clock is released right away.
+    let authorize = Tx::new_authorize(6, 1, dec("3")).with_timestamp(1000);
+    assert_eq!(process(&mut db, &authorize), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("73"), zero, dec("5"), zero, false));
+  }
+
+  #[test]
+  fn authorization_expiry_snapshot() {
+    let zero = Decimal::ZERO;
+
+    let mut db = Db::new().with_authorization_ttl(60);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("10"))), Ok(()));
+    let authorize = Tx::new_authorize(2, 1, dec("5")).with_timestamp(0);
+    assert_eq!(process(&mut db, &authorize), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_authorize(3, 1, dec("3"))), Ok(()));
+    let deposit = Tx::new_deposit(4, 1, dec("1")).with_timestamp(60);
+    assert_eq!(process(&mut db, &deposit), Ok(()));
+
+    // Open and expired authorizations and the clock survive a snapshot.
+    let mut restored = Db::restore(db.snapshot()).unwrap().with_authorization_ttl(60);
+    assert_eq!(balances(&restored, 1), (dec("8"), zero, dec("3"), zero, false));
+    assert_eq!(restored.clock(), Some(60));
+    let account = restored.get_account(ClientId::new(1)).unwrap();
+    assert_eq!(account.authorization(TxId::new(3)).map(|tx| tx.amount()), Some(dec("3")));
+    assert_eq!(
+      process(&mut restored, &Tx::new_capture(2, 1)),
+      Err(TxErrKind::AuthorizationExpired)
+    );
+  }
+
+  #[test]
+  fn authorization_expiry_rewind() {
+    let zero = Decimal::ZERO;
+
+    // A transaction at the expiry sees the released funds, but the expiry only stands if
+    // the transaction is applied.
+    let mut db = Db::new().with_authorization_ttl(60);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("10"))), Ok(()));
+    let authorize = Tx::new_authorize(2, 1, dec("10")).with_timestamp(0);
+    assert_eq!(process(&mut db, &authorize), Ok(()));
+    let withdraw = |tx, amount, timestamp| {
+      Tx::new_withdraw(tx, 1, dec(amount)).with_timestamp(timestamp)
+    };
+    assert_eq!(process(&mut db, &withdraw(3, "10", 59)), Err(TxErrKind::Insufficient));
+    assert_eq!(process(&mut db, &withdraw(4, "11", 60)), Err(TxErrKind::Insufficient));
+    assert_eq!(balances(&db, 1), (zero, zero, dec("10"), zero, false));
+    assert_eq!(db.clock(), Some(0));
+    let account = db.get_account(ClientId::new(1)).unwrap();
+    assert!(account.authorization(TxId::new(2)).is_some());
+    assert_eq!(process(&mut db, &withdraw(5, "10", 60)), Ok(()));
+    assert_eq!(balances(&db, 1), (zero, zero, zero, zero, false));
+    assert_eq!(db.clock(), Some(60));
+  }
+
+  #[test]
+  fn authorization_expiry_clock() {
+    let zero = Decimal::ZERO;
+
+    // Any transaction with a timestamp advances the clock, not only withdrawals.
+    let mut db = Db::new().with_authorization_ttl(60);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("10"))), Ok(()));
+    let authorize = Tx::new_authorize(2, 1, dec("10")).with_timestamp(0);
+    assert_eq!(process(&mut db, &authorize), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_void(2, 1).with_timestamp(60)),
+      Err(TxErrKind::AuthorizationExpired)
+    );
+    assert_eq!(balances(&db, 1), (zero, zero, dec("10"), zero, false));
+    let deposit = Tx::new_deposit(3, 1, dec("5")).with_timestamp(60);
+    assert_eq!(process(&mut db, &deposit), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("15"), zero, zero, zero, false));
+    assert_eq!(db.clock(), Some(60));
+  }
+
+  #[test]
+  fn capture_fees_and_velocity() {
+    let zero = Decimal::ZERO;
+
+    // A capture pays the withdrawal fee and counts towards the velocity limits.
+    let fees = fee_schedule(&[fee_rule(TxType::Withdrawal, "1", "0")]);
+    let limits = velocity(Some("50"), None);
+
+    let mut db = Db::new().with_fee_schedule(fees).with_velocity_limits(limits);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, dec("60"))), Ok(()));
+    let authorize = |amount| Tx::new_authorize(2, 1, dec(amount)).with_timestamp(0);
+    // Nothing would be left to pay the fee.
+    assert_eq!(process(&mut db, &authorize("60")), Err(TxErrKind::Insufficient));
+    assert_eq!(process(&mut db, &authorize("55")), Err(TxErrKind::VelocityExceeded));
+    assert_eq!(process(&mut db, &authorize("40")), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_partial_capture(2, 1, dec("40"))),
+      Err(TxErrKind::MissingTimestamp)
+    );
+    // A withdrawal in the meantime still counts before the capture.
+    let withdraw = |tx, amount, timestamp| {
+      Tx::new_withdraw(tx, 1, dec(amount)).with_timestamp(timestamp)
+    };
+    assert_eq!(process(&mut db, &withdraw(3, "11", 5)), Ok(()));
+    let capture = Tx::new_capture(2, 1).with_timestamp(10);
+    assert_eq!(process(&mut db, &capture), Err(TxErrKind::VelocityExceeded));
+    assert_eq!(balances(&db, 1), (dec("8"), zero, dec("40"), zero, false));
+    // The fee is paid out of the available funds, including the released remainder.
+    let capture = Tx::new_partial_capture(2, 1, dec("30")).with_timestamp(10);
+    assert_eq!(process(&mut db, &capture), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("17"), zero, zero, zero, false));
+    assert_eq!(db.get_account(ClientId::new(9)).unwrap().available(), dec("2"));
+    assert_eq!(
+      process(&mut db, &withdraw(4, "10", 20)),
+      Err(TxErrKind::VelocityExceeded)
+    );
+    assert_eq!(process(&mut db, &withdraw(5, "9", 20)), Ok(()));
+    assert_eq!(balances(&db, 1), (dec("7"), zero, zero, zero, false));
+  }
+
+  #[test]
+  fn snapshot_restore() {
+    let mut db = Db::new().with_journal(true);
+    assert_eq!(process(&mut db, &Tx::new_deposit(1, 1, Decimal::from(10))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_deposit(2, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_withdraw(3, 1, Decimal::from(2))), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_partial_dispute(1, 1, Decimal::from(4))),
+      Ok(())
+    );
+    assert_eq!(process(&mut db, &Tx::new_deposit(4, 2, Decimal::from(5))), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_dispute(4, 2)), Ok(()));
+    assert_eq!(process(&mut db, &Tx::new_chargeback(4, 2)), Ok(()));
+
+    let mut json = Vec::new();
+    db.snapshot().write(&mut json).unwrap();
+    let mut restored = Db::restore(Snapshot::read(json.as_slice()).unwrap()).unwrap();
+
+    assert_eq!(restored.chain(), db.chain());
+    for account in db.accounts() {
+      assert_eq!(restored.get_account(account.id()), Some(account));
+    }
+
+    // Disputes and duplicates refer to transactions from before the snapshot.
+    assert_eq!(process(&mut restored, &Tx::new_resolve(1, 1)), Ok(()));
+    assert_eq!(process(&mut restored, &Tx::new_dispute(2, 1)), Ok(()));
+    assert_eq!(
+      process(&mut restored, &Tx::new_deposit(3, 1, Decimal::from(1))),
+      Err(TxErrKind::Duplicate)
+    );
+    assert_eq!(
+      process(&mut restored, &Tx::new_deposit(5, 2, Decimal::from(1))),
+      Err(TxErrKind::AccountLocked)
+    );
+
+    let account = restored.get_account(ClientId::new(1)).unwrap();
+    assert_eq!(account.available(), Decimal::from(8));
+    assert_eq!(account.held(), Decimal::from(5));
+  }
+
+  #[test]
+  fn opening_balances() {
+    let balance =
+      |client, available: i64, held: i64, total: i64, locked| OpeningBalance {
+        client,
+        available: Decimal::from(available),
+        held: Decimal::from(held),
+        authorized: Decimal::ZERO,
+        total: Decimal::from(total),
+        locked,
+        debt: Decimal::ZERO,
+      };
+
+    let mut db = Db::new();
+    assert_eq!(db.open_account(&balance(1, 5, 2, 7, false)), Ok(()));
+    assert_eq!(db.open_account(&balance(2, 1, 0, 1, true)), Ok(()));
+
+    let err = db.open_account(&balance(1, 5, 2, 7, false)).unwrap_err();
+    assert_eq!(err.kind(), TxErrKind::AccountExists);
+    let err = db.open_account(&balance(3, 5, 2, 8, false)).unwrap_err();
+    assert_eq!(err.kind(), TxErrKind::InconsistentTotal);
+    assert_eq!(err.client(), Some(ClientId::new(3)));
+
+    // Carried authorized funds are rejected rather than handed back.
+    let authorized =
+      OpeningBalance { authorized: Decimal::ONE, ..balance(3, 5, 2, 8, false) };
+    let err = db.open_account(&authorized).unwrap_err();
+    assert_eq!(err.kind(), TxErrKind::PendingAuthorizations);
+    assert!(db.get_account(ClientId::new(3)).is_none());
+
+    assert_eq!(process(&mut db, &Tx::new_withdraw(1, 1, Decimal::from(5))), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_deposit(2, 2, Decimal::from(5))),
+      Err(TxErrKind::AccountLocked)
+    );
+
+    match db.get_account(ClientId::new(1)).unwrap() {
+      AnyAccount::Unlocked(account) => {
+        assert_eq!(account.available(), Decimal::from(0));
+        assert_eq!(account.held(), Decimal::from(2));
+        assert_eq!(account.carried_held(), Decimal::from(2));
+      }
+      AnyAccount::Locked(_) => panic!("Account should be unlocked"),
+    }
+
+    // Carried-over held funds can only be released by an operator.
+    assert_eq!(
+      process(&mut db, &Tx::new_resolve(1, 1)),
+      Err(TxErrKind::MissingTxForClient)
+    );
+    assert_eq!(
+      process(&mut db, &Tx::new_release(3, 1, "", "")),
+      Err(TxErrKind::MissingAdminDetails)
+    );
+    let release = |tx, amount: i64| {
+      Tx::new_partial_release(tx, 1, Decimal::from(amount), "finance", "settled")
+    };
+    assert_eq!(process(&mut db, &release(3, 3)), Err(TxErrKind::InvalidReleaseAmount));
+    assert_eq!(process(&mut db, &release(3, 1)), Ok(()));
+    assert_eq!(process(&mut db, &release(3, 1)), Err(TxErrKind::Duplicate));
+    assert_eq!(process(&mut db, &Tx::new_release(4, 1, "finance", "settled")), Ok(()));
+    assert_eq!(
+      process(&mut db, &Tx::new_release(5, 1, "finance", "settled")),
+      Err(TxErrKind::InvalidReleaseAmount)
+    );
+
+    match db.get_account(ClientId::new(1)).unwrap() {
+      AnyAccount::Unlocked(account) => {
+        assert_eq!(account.available(), Decimal::from(2));
+        assert_eq!(account.held(), Decimal::ZERO);
+        assert_eq!(account.carried_held(), Decimal::ZERO);
+        assert_eq!(account.releases().len(), 2);
+      }
+      AnyAccount::Locked(_) => panic!("Account should be unlocked"),
+    }
   }
 }
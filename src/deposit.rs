@@ -13,18 +13,27 @@
 
 #![warn(clippy::all)]
 
-use crate::{ClientId, TxErr, TxId};
+use crate::{ClientId, TxErrKind, TxId};
 use derive_more::Display;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// A held deposit keeps track of how much of it is disputed, and how much of the disputed
+/// amount could not be held and was recorded as debt instead.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct DepositHeld {
+  disputed: Decimal,
+  debt: Decimal,
+}
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub struct DepositHeld;
-
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub struct DepositReleased;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
-pub struct DepositReversed;
+/// A reversed deposit keeps track of how much of it was charged back.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct DepositReversed {
+  reversed: Decimal,
+}
 
 pub trait DepositState {}
 impl DepositState for DepositHeld {}
@@ -47,7 +56,7 @@ impl DepositState for DepositReversed {}
 ///
 /// * An error is thrown if the amount being deposited would overflow the account's total
 ///   or available balance.
-#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Copy)]
+#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 #[display(fmt = "Deposit {} {} Amount={}", id, client, amount)]
 pub struct Deposit<State: DepositState = DepositReleased> {
   id: TxId,
@@ -74,25 +83,62 @@ impl<State: DepositState> Deposit<State> {
 }
 
 impl Deposit<DepositReleased> {
-  pub fn new(id: TxId, client: ClientId, amount: Decimal) -> Result<Self, TxErr> {
+  pub fn new(id: TxId, client: ClientId, amount: Decimal) -> Result<Self, TxErrKind> {
     if amount.is_sign_negative() {
-      Err(TxErr::NegativeAmount)
+      Err(TxErrKind::NegativeAmount)
     } else {
       Ok(Self { id, client, amount, state: DepositReleased })
     }
   }
 
-  pub fn hold(self) -> Deposit<DepositHeld> {
+  /// Hold *disputed* out of the deposit's amount, of which *debt* could not be held.
+  ///
+  /// The caller must ensure that *disputed* is not larger than the deposit's amount.
+  pub fn hold(self, disputed: Decimal, debt: Decimal) -> Deposit<DepositHeld> {
+    assert!(disputed <= self.amount);
+    assert!(debt <= disputed);
+
     Deposit::<DepositHeld> {
       id: self.id,
       client: self.client,
       amount: self.amount,
-      state: DepositHeld,
+      state: DepositHeld { disputed, debt },
     }
   }
 }
 
 impl Deposit<DepositHeld> {
+  /// Get the disputed part of the deposit's amount.
+  pub fn disputed(&self) -> Decimal {
+    self.state.disputed
+  }
+
+  /// Get the part of the disputed amount that was recorded as debt.
+  pub fn debt(&self) -> Decimal {
+    self.state.debt
+  }
+
+  /// Get the part of the disputed amount that is actually held.
+  pub fn held(&self) -> Decimal {
+    self.state.disputed - self.state.debt
+  }
+
+  /// Get the part of the deposit's amount that is not disputed.
+  pub fn undisputed(&self) -> Decimal {
+    self.amount - self.state.disputed
+  }
+
+  /// Hold an additional *disputed* amount out of the deposit's amount, of which *debt*
+  /// could not be held.
+  ///
+  /// The caller must ensure that *disputed* is not larger than the undisputed amount.
+  pub fn hold_more(&mut self, disputed: Decimal, debt: Decimal) {
+    assert!(disputed <= self.undisputed());
+    assert!(debt <= disputed);
+    self.state.disputed += disputed;
+    self.state.debt += debt;
+  }
+
   pub fn release(self) -> Deposit<DepositReleased> {
     Deposit::<DepositReleased> {
       id: self.id,
@@ -107,14 +153,21 @@ impl Deposit<DepositHeld> {
       id: self.id,
       client: self.client,
       amount: self.amount,
-      state: DepositReversed,
+      state: DepositReversed { reversed: self.state.disputed },
     }
   }
 }
 
+impl Deposit<DepositReversed> {
+  /// Get the part of the deposit's amount that was charged back.
+  pub fn reversed(&self) -> Decimal {
+    self.state.reversed
+  }
+}
+
 #[cfg(test)]
 mod deposit_tests {
-  use crate::{deposit::DepositReleased, ClientId, Deposit, TxErr, TxId};
+  use crate::{deposit::DepositReleased, ClientId, Deposit, TxErrKind, TxId};
   use rust_decimal::Decimal;
 
   #[test]
@@ -133,7 +186,23 @@ mod deposit_tests {
   fn negative_amount() {
     assert_eq!(
       Deposit::new(TxId::new(1), ClientId::new(1), Decimal::from(-5)),
-      Err(TxErr::NegativeAmount)
+      Err(TxErrKind::NegativeAmount)
     );
   }
+
+  #[test]
+  fn partial_holds() {
+    let tx = Deposit::new(TxId::new(1), ClientId::new(1), Decimal::from(5)).unwrap();
+
+    let mut tx = tx.hold(Decimal::from(2), Decimal::ZERO);
+    assert_eq!(tx.disputed(), Decimal::from(2));
+    assert_eq!(tx.undisputed(), Decimal::from(3));
+
+    tx.hold_more(Decimal::from(3), Decimal::from(1));
+    assert_eq!(tx.disputed(), Decimal::from(5));
+    assert_eq!(tx.held(), Decimal::from(4));
+    assert_eq!(tx.debt(), Decimal::from(1));
+    assert_eq!(tx.undisputed(), Decimal::from(0));
+    assert_eq!(tx.reverse().reversed(), Decimal::from(5));
+  }
 }
@@ -13,13 +13,16 @@
 
 #![warn(clippy::all)]
 
-use crate::{ClientId, TxId};
+use crate::{ClientId, TxErrKind, TxId};
 use derive_more::Display;
-use derive_new::new;
+use rust_decimal::Decimal;
 
 /// A dispute is a claim of an erroneous transaction by the client.
 ///
-/// A dispute must decrease the available funds and increase held funds.
+/// A dispute of a deposit must decrease the available funds and increase held funds.
+///
+/// A dispute of a withdrawal must increase held funds, putting the withdrawn amount back
+/// into the account until the dispute is settled.
 ///
 /// # Errors
 ///
@@ -28,24 +31,49 @@ use derive_new::new;
 /// * An error is thrown if the [transaction ID](TxId) does not already exist.
 ///
 /// * An error is thrown if the [transaction ID](TxId) does not refer to a released
-///   deposit transaction associated with [the corresponding client](ClientId).
+///   deposit or withdrawal transaction associated with [the corresponding
+///   client](ClientId).
+///
+/// * An error is thrown if the disputed amount is negative.
+///
+/// * An error is thrown if the disputed amount is zero or larger than the part of the
+///   transaction that is not already disputed.
 ///
-/// * An error is thrown if the amount being disputed is larger than the available balance
-///   in the client's account.
+/// * An error is thrown if the amount of a deposit being disputed is larger than the
+///   available balance in the client's account.
+///
+/// * An error is thrown if the amount of a withdrawal being disputed would overflow the
+///   account's total balance.
 ///
 /// # Notes
 ///
-/// * The amount being disputed cannot overflow the held funds since it refers to a
-///   pre-existing transaction and it was checked that the available and total funds
-///   cannot overflow during the entrance of said transaction.
-#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Copy, new)]
-#[display(fmt = "Dispute {} {}", id, client)]
+/// * The amount of a deposit being disputed cannot overflow the held funds since it
+///   refers to a pre-existing transaction and it was checked that the available and total
+///   funds cannot overflow during the entrance of said transaction.
+///
+/// * A dispute without an amount covers the part of the transaction that is not already
+///   disputed. A dispute with an amount covers only that amount, and several such
+///   disputes on the same transaction add up.
+#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Copy)]
+#[display(fmt = "Dispute {} {} Amount={:?}", id, client, amount)]
 pub struct Dispute {
   id: TxId,
   client: ClientId,
+  amount: Option<Decimal>,
 }
 
 impl Dispute {
+  pub fn new(
+    id: TxId,
+    client: ClientId,
+    amount: Option<Decimal>,
+  ) -> Result<Self, TxErrKind> {
+    match amount {
+      Some(amount) if amount.is_sign_negative() => Err(TxErrKind::NegativeAmount),
+      _ => Ok(Self { id, client, amount }),
+    }
+  }
+
   /// Get the dispute's id.
   pub fn id(&self) -> TxId {
     self.id
@@ -55,4 +83,9 @@ impl Dispute {
   pub fn client(&self) -> ClientId {
     self.client
   }
+
+  /// Get the dispute's amount, if it only covers part of the transaction.
+  pub fn amount(&self) -> Option<Decimal> {
+    self.amount
+  }
 }
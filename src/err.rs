@@ -13,11 +13,16 @@
 
 #![warn(clippy::all)]
 
+use crate::{ClientId, TxId};
 use derive_more::Display;
+use rust_decimal::Decimal;
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+use std::error::Error;
+use std::fmt;
 
-#[derive(Display, Debug, PartialEq, Eq)]
-#[display(fmt = "{}")]
-pub enum TxErr {
+/// The kind of a [transaction error](TxErr).
+#[derive(Display, Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum TxErrKind {
   #[display(fmt = "Transaction must provide an amount")]
   MissingAmount,
 
@@ -30,6 +35,15 @@ pub enum TxErr {
   #[display(fmt = "Attempt to access a non-existing account")]
   AccessUnavailable,
 
+  #[display(fmt = "Attempt to access a locked account")]
+  AccountLocked,
+
+  #[display(fmt = "Attempt to unlock an account that is not locked")]
+  AccountNotLocked,
+
+  #[display(fmt = "Administrative transaction must provide an operator and a reason")]
+  MissingAdminDetails,
+
   #[display(fmt = "Transaction would overflow account")]
   Overflow,
 
@@ -44,6 +58,261 @@ pub enum TxErr {
 
   #[display(fmt = "Referenced transaction does not belong to client")]
   MissingTxForClient,
+
+  #[display(fmt = "Disputed amount is zero or exceeds the undisputed amount")]
+  InvalidDisputeAmount,
+
+  #[display(fmt = "Total is not the sum of available, held and authorized funds")]
+  InconsistentTotal,
+
+  #[display(fmt = "Account already exists")]
+  AccountExists,
+
+  #[display(fmt = "Amount has more decimal places than allowed")]
+  ExcessivePrecision,
+
+  #[display(fmt = "Invalid zero amount")]
+  ZeroAmount,
+
+  #[display(fmt = "Transfer must provide a destination client")]
+  MissingDestination,
+
+  #[display(fmt = "Transfer source and destination are the same client")]
+  SelfTransfer,
+
+  #[display(fmt = "Transaction would exceed the credit limit")]
+  CreditLimitExceeded,
+
+  #[display(fmt = "Withdrawal would exceed the velocity limits")]
+  VelocityExceeded,
+
+  #[display(fmt = "Withdrawal must provide a timestamp for the velocity limits")]
+  MissingTimestamp,
+
+  #[display(fmt = "Authorization has expired")]
+  AuthorizationExpired,
+
+  #[display(fmt = "Captured amount exceeds the authorized amount")]
+  ExcessiveCapture,
+
+  #[display(fmt = "Released amount is zero or exceeds the carried-over held funds")]
+  InvalidReleaseAmount,
+
+  #[display(fmt = "Opening balance has authorized funds of unknown authorizations")]
+  PendingAuthorizations,
+
+  #[display(
+    fmt = "Withdrawal is a day or more behind the latest one for the velocity limits"
+  )]
+  StaleTimestamp,
+}
+
+impl TxErrKind {
+  /// A stable, machine-readable code for the error.
+  pub fn code(&self) -> &'static str {
+    match self {
+      TxErrKind::MissingAmount => "missing_amount",
+      TxErrKind::NegativeAmount => "negative_amount",
+      TxErrKind::Insufficient => "insufficient_funds",
+      TxErrKind::AccessUnavailable => "unknown_account",
+      TxErrKind::AccountLocked => "account_locked",
+      TxErrKind::AccountNotLocked => "account_not_locked",
+      TxErrKind::MissingAdminDetails => "missing_admin_details",
+      TxErrKind::Overflow => "overflow",
+      TxErrKind::Duplicate => "duplicate_tx",
+      TxErrKind::ExtraneousAmount => "extraneous_amount",
+      TxErrKind::MissingTx => "unknown_tx",
+      TxErrKind::MissingTxForClient => "unknown_tx_for_client",
+      TxErrKind::InvalidDisputeAmount => "invalid_dispute_amount",
+      TxErrKind::InconsistentTotal => "inconsistent_total",
+      TxErrKind::AccountExists => "account_exists",
+      TxErrKind::ExcessivePrecision => "excessive_precision",
+      TxErrKind::ZeroAmount => "zero_amount",
+      TxErrKind::MissingDestination => "missing_destination",
+      TxErrKind::SelfTransfer => "self_transfer",
+      TxErrKind::CreditLimitExceeded => "credit_limit_exceeded",
+      TxErrKind::VelocityExceeded => "velocity_exceeded",
+      TxErrKind::MissingTimestamp => "missing_timestamp",
+      TxErrKind::AuthorizationExpired => "authorization_expired",
+      TxErrKind::ExcessiveCapture => "excessive_capture",
+      TxErrKind::InvalidReleaseAmount => "invalid_release_amount",
+      TxErrKind::PendingAuthorizations => "pending_authorizations",
+      TxErrKind::StaleTimestamp => "stale_timestamp",
+    }
+  }
+
+  /// A stable numeric code for the error.
+  ///
+  /// Numbers are never reused: new kinds of errors get new numbers.
+  pub fn number(&self) -> u16 {
+    match self {
+      TxErrKind::MissingAmount => 1,
+      TxErrKind::NegativeAmount => 2,
+      TxErrKind::Insufficient => 3,
+      TxErrKind::AccessUnavailable => 4,
+      TxErrKind::Overflow => 5,
+      TxErrKind::Duplicate => 6,
+      TxErrKind::ExtraneousAmount => 7,
+      TxErrKind::MissingTx => 8,
+      TxErrKind::MissingTxForClient => 9,
+      TxErrKind::InvalidDisputeAmount => 10,
+      TxErrKind::AccountLocked => 11,
+      TxErrKind::AccountNotLocked => 12,
+      TxErrKind::MissingAdminDetails => 13,
+      TxErrKind::InconsistentTotal => 14,
+      TxErrKind::AccountExists => 15,
+      TxErrKind::ExcessivePrecision => 16,
+      TxErrKind::ZeroAmount => 17,
+      TxErrKind::MissingDestination => 18,
+      TxErrKind::SelfTransfer => 19,
+      TxErrKind::CreditLimitExceeded => 20,
+      TxErrKind::VelocityExceeded => 21,
+      TxErrKind::MissingTimestamp => 22,
+      TxErrKind::AuthorizationExpired => 23,
+      TxErrKind::ExcessiveCapture => 24,
+      TxErrKind::InvalidReleaseAmount => 25,
+      TxErrKind::PendingAuthorizations => 26,
+      // 27 was a second number for missing administrative details, merged into 13.
+      TxErrKind::StaleTimestamp => 28,
+    }
+  }
+}
+
+impl Serialize for TxErrKind {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(self.code())
+  }
+}
+
+/// An error that occurred while processing a transaction, along with its context.
+///
+/// The context is whatever was known when the error occurred: the transaction and client
+/// IDs, the requested amount, and the balances of the related account (if it exists). The
+/// balances are unaffected by the failed transaction.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TxErr {
+  kind: TxErrKind,
+  tx: Option<TxId>,
+  client: Option<ClientId>,
+  amount: Option<Decimal>,
+  available: Option<Decimal>,
+  held: Option<Decimal>,
+}
+
+impl TxErr {
+  pub fn new(kind: TxErrKind) -> Self {
+    Self { kind, tx: None, client: None, amount: None, available: None, held: None }
+  }
+
+  /// Attach the transaction's IDs and requested amount to the error.
+  pub fn with_tx(mut self, tx: TxId, client: ClientId, amount: Option<Decimal>) -> Self {
+    self.tx = Some(tx);
+    self.client = Some(client);
+    self.amount = amount;
+    self
+  }
+
+  /// Attach the related client's ID to the error.
+  pub fn with_client(mut self, client: ClientId) -> Self {
+    self.client = Some(client);
+    self
+  }
+
+  /// Attach the related account's balances to the error.
+  pub fn with_balances(mut self, available: Decimal, held: Decimal) -> Self {
+    self.available = Some(available);
+    self.held = Some(held);
+    self
+  }
+
+  /// Get the error's kind.
+  pub fn kind(&self) -> TxErrKind {
+    self.kind
+  }
+
+  /// A stable, machine-readable code for the error.
+  pub fn code(&self) -> &'static str {
+    self.kind.code()
+  }
+
+  /// A stable numeric code for the error.
+  pub fn number(&self) -> u16 {
+    self.kind.number()
+  }
+
+  /// Get the ID of the failed transaction.
+  pub fn tx(&self) -> Option<TxId> {
+    self.tx
+  }
+
+  /// Get the ID of the failed transaction's client.
+  pub fn client(&self) -> Option<ClientId> {
+    self.client
+  }
+
+  /// Get the amount requested by the failed transaction.
+  pub fn amount(&self) -> Option<Decimal> {
+    self.amount
+  }
+
+  /// Get the related account's available funds at the time of the failure.
+  pub fn available(&self) -> Option<Decimal> {
+    self.available
+  }
+
+  /// Get the related account's held funds at the time of the failure.
+  pub fn held(&self) -> Option<Decimal> {
+    self.held
+  }
+}
+
+impl fmt::Display for TxErr {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "{}", self.kind)
+  }
+}
+
+impl Error for TxErr {}
+
+impl From<TxErrKind> for TxErr {
+  fn from(kind: TxErrKind) -> Self {
+    Self::new(kind)
+  }
+}
+
+impl Serialize for TxErr {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    let mut state = serializer.serialize_struct("TxErr", 8)?;
+    state.serialize_field("code", &self.code())?;
+    state.serialize_field("number", &self.number())?;
+    state.serialize_field("message", &self.kind.to_string())?;
+    state.serialize_field("tx", &self.tx)?;
+    state.serialize_field("client", &self.client)?;
+    state.serialize_field("amount", &self.amount)?;
+    state.serialize_field("available", &self.available)?;
+    state.serialize_field("held", &self.held)?;
+    state.end()
+  }
 }
 
 pub type TxResult = Result<(), TxErr>;
+
+#[cfg(test)]
+mod err_tests {
+  use crate::{ClientId, TxErr, TxErrKind, TxId};
+  use rust_decimal::Decimal;
+
+  #[test]
+  fn serialize() {
+    let err = TxErr::new(TxErrKind::Insufficient)
+      .with_tx(TxId::new(2), ClientId::new(1), Some(Decimal::from(10)))
+      .with_balances(Decimal::from(4), Decimal::from(1));
+
+    assert_eq!(
+      serde_json::to_string(&err).unwrap(),
+      r#"{"code":"insufficient_funds","number":3,"message":"Insufficient funds","tx":2,"#
+        .to_owned()
+        + r#""client":1,"amount":"10","available":"4","held":"1"}"#
+    );
+  }
+}
@@ -0,0 +1,214 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{ClientId, Rounding, TxErr, TxErrKind, TxResult, TxType, MAX_SCALE};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+
+/// A fee rule: a fixed amount plus a percentage of the amount of a transaction, capped by
+/// an optional minimum and maximum.
+///
+/// # Errors
+///
+/// * An error is thrown if any of the amounts is negative.
+///
+/// # Notes
+///
+/// * If the maximum is below the minimum, the fee is the maximum.
+/// * A missing or empty fixed amount or percentage is zero.
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FeeRule {
+  #[serde(rename = "type")]
+  pub typ: TxType,
+  #[serde(default, deserialize_with = "zero_if_empty")]
+  pub fixed: Decimal,
+  #[serde(default, deserialize_with = "zero_if_empty")]
+  pub percent: Decimal,
+  #[serde(default)]
+  pub min: Option<Decimal>,
+  #[serde(default)]
+  pub max: Option<Decimal>,
+}
+
+/// Deserialize an amount that is zero if its cell is empty.
+fn zero_if_empty<'de, D: Deserializer<'de>>(
+  deserializer: D,
+) -> Result<Decimal, D::Error> {
+  Ok(Option::<Decimal>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+impl FeeRule {
+  /// Compute the fee for a transaction of amount *amount*, before rounding.
+  fn fee(&self, amount: Decimal) -> Result<Decimal, TxErrKind> {
+    let percent = amount.checked_mul(self.percent).ok_or(TxErrKind::Overflow)?;
+    let mut fee = self
+      .fixed
+      .checked_add(percent / Decimal::ONE_HUNDRED)
+      .ok_or(TxErrKind::Overflow)?;
+
+    if let Some(min) = self.min {
+      fee = fee.max(min);
+    }
+
+    if let Some(max) = self.max {
+      fee = fee.min(max);
+    }
+
+    Ok(fee)
+  }
+}
+
+/// The fees charged on transactions, by type of transaction, and the account they are
+/// credited to.
+///
+/// Fees are rounded to [MAX_SCALE] decimal places. A [database](crate::Db) charges fees
+/// on withdrawals and chargebacks, rules for other types of transactions are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeeSchedule {
+  account: ClientId,
+  rounding: Rounding,
+  rules: HashMap<TxType, FeeRule>,
+}
+
+impl FeeSchedule {
+  /// Create an empty schedule crediting fees to the account of client *account*.
+  pub fn new(account: ClientId) -> Self {
+    Self { account, rounding: Rounding::default(), rules: HashMap::new() }
+  }
+
+  /// Set how to round fees to [MAX_SCALE] decimal places.
+  pub fn with_rounding(mut self, rounding: Rounding) -> Self {
+    self.rounding = rounding;
+    self
+  }
+
+  /// Add a rule, replacing any previous rule for the same type of transaction.
+  pub fn add_rule(&mut self, rule: &FeeRule) -> TxResult {
+    let amounts = [Some(rule.fixed), Some(rule.percent), rule.min, rule.max];
+
+    if amounts.iter().flatten().any(Decimal::is_sign_negative) {
+      return Err(TxErr::new(TxErrKind::NegativeAmount));
+    }
+
+    self.rules.insert(rule.typ, *rule);
+    Ok(())
+  }
+
+  /// The client whose account fees are credited to.
+  pub fn account(&self) -> ClientId {
+    self.account
+  }
+
+  pub fn rounding(&self) -> Rounding {
+    self.rounding
+  }
+
+  /// Get the rule for transactions of type *typ*, if there is one.
+  pub fn rule(&self, typ: TxType) -> Option<&FeeRule> {
+    self.rules.get(&typ)
+  }
+
+  /// Compute the fee for a transaction of type *typ* and amount *amount*, which is zero
+  /// if there is no rule for the type.
+  pub fn fee(&self, typ: TxType, amount: Decimal) -> Result<Decimal, TxErrKind> {
+    match self.rules.get(&typ) {
+      Some(rule) => Ok(self.rounding.round(rule.fee(amount)?, MAX_SCALE)),
+      None => Ok(Decimal::ZERO),
+    }
+  }
+}
+
+#[cfg(test)]
+mod fee_tests {
+  use crate::{ClientId, FeeRule, FeeSchedule, Rounding, TxErrKind, TxType};
+  use rust_decimal::Decimal;
+  use std::str::FromStr;
+
+  fn dec(s: &str) -> Decimal {
+    Decimal::from_str(s).unwrap()
+  }
+
+  #[test]
+  fn fees() {
+    let mut fees = FeeSchedule::new(ClientId::new(0)).with_rounding(Rounding::HalfUp);
+
+    let withdrawal = FeeRule {
+      typ: TxType::Withdrawal,
+      fixed: dec("0.25"),
+      percent: dec("1.5"),
+      min: Some(dec("0.5")),
+      max: Some(dec("10")),
+    };
+    assert!(fees.add_rule(&withdrawal).is_ok());
+
+    let chargeback = FeeRule {
+      typ: TxType::Chargeback,
+      fixed: dec("15"),
+      percent: Decimal::ZERO,
+      min: None,
+      max: None,
+    };
+    assert!(fees.add_rule(&chargeback).is_ok());
+
+    // The minimum applies.
+    assert_eq!(fees.fee(TxType::Withdrawal, dec("10")), Ok(dec("0.5")));
+    // The fixed amount plus the percentage, rounded half up.
+    assert_eq!(fees.fee(TxType::Withdrawal, dec("100.01")), Ok(dec("1.7502")));
+    // The maximum applies.
+    assert_eq!(fees.fee(TxType::Withdrawal, dec("1000")), Ok(dec("10")));
+    // A flat fee.
+    assert_eq!(fees.fee(TxType::Chargeback, dec("1000")), Ok(dec("15")));
+    // No rule.
+    assert_eq!(fees.fee(TxType::Deposit, dec("1000")), Ok(Decimal::ZERO));
+
+    let negative = FeeRule { typ: TxType::Deposit, fixed: dec("-1"), ..withdrawal };
+    assert_eq!(
+      fees.add_rule(&negative).map_err(|err| err.kind()),
+      Err(TxErrKind::NegativeAmount)
+    );
+    assert_eq!(fees.rule(TxType::Deposit), None);
+
+    let overflow = FeeRule { percent: dec("200"), ..withdrawal };
+    assert!(fees.add_rule(&overflow).is_ok());
+    assert_eq!(fees.fee(TxType::Withdrawal, Decimal::MAX), Err(TxErrKind::Overflow));
+  }
+
+  #[test]
+  fn deserialize() {
+    let rules = "percent, type,       fixed, max\n\
+                 ,        chargeback, 10,    \n\
+                 1.5,     withdrawal, ,      10\n";
+    let mut reader =
+      csv::ReaderBuilder::new().trim(csv::Trim::All).from_reader(rules.as_bytes());
+    let rules = reader.deserialize().collect::<Result<Vec<FeeRule>, _>>().unwrap();
+
+    let chargeback = FeeRule {
+      typ: TxType::Chargeback,
+      fixed: dec("10"),
+      percent: Decimal::ZERO,
+      min: None,
+      max: None,
+    };
+    let withdrawal = FeeRule {
+      typ: TxType::Withdrawal,
+      fixed: Decimal::ZERO,
+      percent: dec("1.5"),
+      min: None,
+      max: Some(dec("10")),
+    };
+    assert_eq!(rules, [chargeback, withdrawal]);
+  }
+}
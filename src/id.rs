@@ -15,18 +15,58 @@
 
 use derive_more::Display;
 use derive_new::new;
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 
 /// A client ID is a u16 as defined by the spec.
 ///
 /// We use a newtype to make it harder to use as a normal u16 value.
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Serialize, Display, new)]
+#[derive(
+  Debug,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Ord,
+  Hash,
+  Clone,
+  Copy,
+  Serialize,
+  Deserialize,
+  Display,
+  new,
+)]
 #[display(fmt = "Client={}", _0)]
 pub struct ClientId(u16);
 
+impl ClientId {
+  /// Get the client ID as a plain u16 value.
+  pub fn value(&self) -> u16 {
+    self.0
+  }
+}
+
 /// A transaction ID is a u32 as defined by the spec.
 ///
 /// We use a newtype to make it harder to use as a normal u32 value.
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Display, new)]
+#[derive(
+  Debug,
+  PartialEq,
+  Eq,
+  PartialOrd,
+  Ord,
+  Hash,
+  Clone,
+  Copy,
+  Serialize,
+  Deserialize,
+  Display,
+  new,
+)]
 #[display(fmt = "Tx={}", _0)]
 pub struct TxId(u32);
+
+impl TxId {
+  /// Get the transaction ID as a plain u32 value.
+  pub fn value(&self) -> u32 {
+    self.0
+  }
+}
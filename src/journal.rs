@@ -0,0 +1,170 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{TxId, TxType};
+use derive_more::Display;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// What a journal entry records.
+#[derive(Debug, Display, PartialEq, Eq, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryKind {
+  /// The effect of the transaction itself.
+  #[default]
+  #[display(fmt = "transaction")]
+  Transaction,
+
+  /// A fee charged or collected because of the transaction (see
+  /// [FeeSchedule](crate::FeeSchedule)).
+  #[display(fmt = "fee")]
+  Fee,
+
+  /// The release of an [authorization](crate::Authorization) that expired (see
+  /// [Db::with_authorization_ttl](crate::Db::with_authorization_ttl)).
+  #[display(fmt = "expiry")]
+  Expiry,
+}
+
+/// An entry in an account's journal, recording the effect of an applied transaction.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct JournalEntry {
+  tx: TxId,
+  #[serde(rename = "type")]
+  typ: TxType,
+  #[serde(default)]
+  kind: EntryKind,
+  available_delta: Decimal,
+  held_delta: Decimal,
+  available: Decimal,
+  held: Decimal,
+  #[serde(default)]
+  authorized_delta: Decimal,
+  #[serde(default)]
+  authorized: Decimal,
+}
+
+impl JournalEntry {
+  /// Create an entry for transaction *tx* which took the account's balances from
+  /// *before* to *after*, both given as `(available, held, authorized)`.
+  pub fn new(
+    tx: TxId,
+    typ: TxType,
+    before: (Decimal, Decimal, Decimal),
+    after: (Decimal, Decimal, Decimal),
+  ) -> Self {
+    Self {
+      tx,
+      typ,
+      kind: EntryKind::Transaction,
+      available_delta: after.0 - before.0,
+      held_delta: after.1 - before.1,
+      available: after.0,
+      held: after.1,
+      authorized_delta: after.2 - before.2,
+      authorized: after.2,
+    }
+  }
+
+  /// Create an entry for a fee charged or collected because of transaction *tx*, which
+  /// took the account's balances from *before* to *after*.
+  pub fn fee(
+    tx: TxId,
+    typ: TxType,
+    before: (Decimal, Decimal, Decimal),
+    after: (Decimal, Decimal, Decimal),
+  ) -> Self {
+    Self { kind: EntryKind::Fee, ..Self::new(tx, typ, before, after) }
+  }
+
+  /// Create an entry for the expiry of authorization *tx*, which took the account's
+  /// balances from *before* to *after*.
+  pub fn expiry(
+    tx: TxId,
+    before: (Decimal, Decimal, Decimal),
+    after: (Decimal, Decimal, Decimal),
+  ) -> Self {
+    Self { kind: EntryKind::Expiry, ..Self::new(tx, TxType::Authorize, before, after) }
+  }
+
+  /// Get the entry's transaction id, which for a fee is the id of the transaction it was
+  /// charged for.
+  pub fn tx(&self) -> TxId {
+    self.tx
+  }
+
+  /// Get the entry's transaction type.
+  pub fn typ(&self) -> TxType {
+    self.typ
+  }
+
+  pub fn kind(&self) -> EntryKind {
+    self.kind
+  }
+
+  /// Get the change to the available funds.
+  pub fn available_delta(&self) -> Decimal {
+    self.available_delta
+  }
+
+  /// Get the change to the held funds.
+  pub fn held_delta(&self) -> Decimal {
+    self.held_delta
+  }
+
+  /// Get the change to the authorized funds.
+  pub fn authorized_delta(&self) -> Decimal {
+    self.authorized_delta
+  }
+
+  /// Get the available funds after the transaction.
+  pub fn available(&self) -> Decimal {
+    self.available
+  }
+
+  /// Get the held funds after the transaction.
+  pub fn held(&self) -> Decimal {
+    self.held
+  }
+
+  /// Get the authorized funds after the transaction.
+  pub fn authorized(&self) -> Decimal {
+    self.authorized
+  }
+}
+
+impl fmt::Display for JournalEntry {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    match self.kind {
+      EntryKind::Transaction => {}
+      EntryKind::Fee => write!(f, "fee on ")?,
+      EntryKind::Expiry => write!(f, "expiry of ")?,
+    }
+
+    write!(
+      f,
+      "{} {} Available={} ({:+}) Held={} ({:+}) Authorized={} ({:+})",
+      self.typ,
+      self.tx,
+      self.available,
+      self.available_delta,
+      self.held,
+      self.held_delta,
+      self.authorized,
+      self.authorized_delta
+    )
+  }
+}
@@ -14,23 +14,59 @@
 #![warn(clippy::all)]
 
 pub mod account;
+pub mod authorization;
+pub mod capture;
+pub mod chain;
 pub mod chargeback;
 pub mod db;
 pub mod deposit;
 pub mod dispute;
 pub mod err;
+pub mod fee;
 pub mod id;
+pub mod journal;
+pub mod limit;
+pub mod opening;
+pub mod outcome;
+pub mod policy;
+pub mod release;
 pub mod resolve;
+pub mod rounding;
+pub mod snapshot;
+pub mod transfer;
 pub mod tx;
+pub mod unlock;
+pub mod velocity;
+pub mod void;
+pub mod wal;
 pub mod withdraw;
 
-pub use crate::account::{Account, AccountLocked, AccountUnlocked};
+pub use crate::account::{
+  Account, AccountLocked, AccountUnlocked, AnyAccount, RoundedAccount,
+};
+pub use crate::authorization::Authorization;
+pub use crate::capture::Capture;
+pub use crate::chain::{Chain, Digest};
 pub use crate::chargeback::Chargeback;
 pub use crate::db::Db;
 pub use crate::deposit::{Deposit, DepositHeld, DepositReleased, DepositReversed};
 pub use crate::dispute::Dispute;
-pub use crate::err::{TxErr, TxResult};
+pub use crate::err::{TxErr, TxErrKind, TxResult};
+pub use crate::fee::{FeeRule, FeeSchedule};
 pub use crate::id::{ClientId, TxId};
+pub use crate::journal::{EntryKind, JournalEntry};
+pub use crate::limit::CreditLimit;
+pub use crate::opening::OpeningBalance;
+pub use crate::outcome::{Effect, Observer, Outcome, Transition, TxState};
+pub use crate::policy::{DisputePolicy, PrecisionPolicy, MAX_SCALE};
+pub use crate::release::Release;
 pub use crate::resolve::Resolve;
-pub use crate::tx::{Tx, TxType};
-pub use crate::withdraw::Withdraw;
+pub use crate::rounding::Rounding;
+pub use crate::snapshot::{Snapshot, SnapshotErr, SNAPSHOT_VERSION};
+pub use crate::transfer::Transfer;
+pub use crate::tx::{Transaction, Tx, TxType};
+pub use crate::unlock::Unlock;
+pub use crate::velocity::{VelocityLimits, VelocityRule};
+pub use crate::void::Void;
+pub use crate::wal::{Wal, WalConfig, WalErr, WalRecord};
+pub use crate::withdraw::{Withdraw, WithdrawHeld, WithdrawReleased, WithdrawReversed};
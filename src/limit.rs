@@ -0,0 +1,37 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// A client's credit limit: how far withdrawals may take the available funds of their
+/// account below zero.
+///
+/// Credit limits are set on a [database](crate::Db) directly or with a `limit`
+/// transaction, either of which creates the account if needed.
+///
+/// # Errors
+///
+/// * An error is thrown if the limit is negative.
+///
+/// # Notes
+///
+/// * Lowering a limit below the credit already in use does not affect the balances, it
+///   only prevents further withdrawals.
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct CreditLimit {
+  pub client: u16,
+  pub limit: Decimal,
+}
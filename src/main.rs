@@ -16,11 +16,25 @@
 use clap::Parser;
 use derive_more::{Display, From};
 use log::{debug, error, info, trace, warn};
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::fmt;
-use std::fs::File;
-use std::io;
-use std::path::PathBuf;
-use tx_engine::{ClientId, Db, TxErr};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use tx_engine::{
+  ClientId, CreditLimit, Db, Digest, DisputePolicy, FeeRule, FeeSchedule, OpeningBalance,
+  PrecisionPolicy, Rounding, Snapshot, SnapshotErr, Tx, TxErr, TxErrKind, VelocityLimits,
+  VelocityRule, Wal, WalConfig, WalErr, WalRecord,
+};
+
+/// The fields of a transaction.
+const TX_FIELDS: [&str; 8] =
+  ["type", "client", "tx", "amount", "destination", "operator", "reason", "timestamp"];
 
 const LICENSE: &str = include_str!("../LICENSE");
 const LICENSE_DEPS: &str = include_str!("../LICENSE.dependencies");
@@ -29,20 +43,286 @@ const LICENSE_DEPS: &str = include_str!("../LICENSE.dependencies");
 #[clap(author, version, about, long_about = None)]
 struct Opt {
   /// Verbose output (can be specified multiple times)
-  #[clap(short, long, action = clap::ArgAction::Count)]
+  #[clap(short, long, action = clap::ArgAction::Count, global = true)]
   verbose: u8,
 
-  /// Show licenses (stops program execution).
-  #[clap(short, long)]
-  license: bool,
+  #[clap(subcommand)]
+  command: Command,
+}
+
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, clap::Subcommand)]
+enum Command {
+  /// Show licenses.
+  License,
+
+  /// Process transactions and output the balances of all accounts.
+  Process(ProcessOpt),
+
+  /// Check transactions for problems without outputting any balances, reporting every
+  /// problem with its line number and a count per category.
+  Validate(ValidateOpt),
+}
+
+#[derive(Debug, clap::Args)]
+struct ProcessOpt {
+  #[clap(flatten)]
+  db: DbOpt,
+
+  /// Write the head of the hash chain over all applied transactions to the standard
+  /// error, as a `head=<DIGEST>` line, keeping it out of the output.
+  #[clap(long)]
+  head: bool,
+
+  /// Write the hash chain (input line, transaction ID and digest of every applied
+  /// transaction) to a CSV file.
+  #[clap(long, value_name = "FILE")]
+  chain_log: Option<PathBuf>,
+
+  /// Recompute the hash chain from the input and compare it against a chain log written
+  /// by --chain-log, reporting the first transaction where they diverge (stops program
+  /// execution). Verification has no side effects, so it cannot be combined with the
+  /// options that write files.
+  #[clap(
+    long,
+    value_name = "FILE",
+    conflicts_with_all = ["wal", "rejects", "chain_log", "save_snapshot", "statements"]
+  )]
+  verify_chain: Option<PathBuf>,
+
+  /// Recompute the hash chain from the input and compare its head against a claimed head
+  /// digest (stops program execution). Like --verify-chain, it cannot be combined with
+  /// the options that write files.
+  #[clap(
+    long,
+    value_name = "DIGEST",
+    conflicts_with_all = [
+      "verify_chain",
+      "wal",
+      "rejects",
+      "chain_log",
+      "save_snapshot",
+      "statements"
+    ]
+  )]
+  verify_head: Option<Digest>,
+
+  /// Keep a journal of every account and write it as a CSV statement per client
+  /// (`<DIR>/client-<ID>.csv`) into an existing directory.
+  #[clap(long, value_name = "DIR")]
+  statements: Option<PathBuf>,
+
+  /// Write every rejected input row to a CSV file, along with its line number, a
+  /// machine-readable reason code and a human-readable message.
+  #[clap(long, value_name = "FILE")]
+  rejects: Option<PathBuf>,
+
+  /// Save the state to a snapshot file after processing the input.
+  #[clap(long, value_name = "FILE")]
+  save_snapshot: Option<PathBuf>,
+
+  /// Durably record every applied transaction in a write-ahead log file. Transactions
+  /// already in the log are replayed on startup (after loading the snapshot, if any).
+  #[clap(long, value_name = "FILE")]
+  wal: Option<PathBuf>,
+
+  /// Stop at the first row that cannot be parsed or processed, and exit with an error
+  /// instead of outputting the balances.
+  #[clap(long)]
+  strict: bool,
+
+  /// Stop once more than N rows have been rejected, and exit with an error instead of
+  /// outputting the balances.
+  #[clap(long, value_name = "N")]
+  max_errors: Option<u64>,
+
+  /// Format of the output balances.
+  #[clap(long, value_enum, default_value_t = OutputFormat::Csv)]
+  output_format: OutputFormat,
+
+  /// Number of decimal places of the output amounts. Amounts with too many digits to get
+  /// them all are an error.
+  #[clap(
+    long,
+    value_name = "N",
+    default_value_t = 4,
+    value_parser = clap::value_parser!(u32).range(0..=28)
+  )]
+  scale: u32,
+
+  /// How to round the output amounts to the number of decimal places given by --scale.
+  #[clap(long, value_name = "half-even|half-up|truncate", default_value_t)]
+  rounding: Rounding,
+
+  #[clap(flatten)]
+  input: InputOpt,
+}
+
+#[derive(Debug, clap::Args)]
+struct ValidateOpt {
+  #[clap(flatten)]
+  db: DbOpt,
+
+  #[clap(flatten)]
+  input: InputOpt,
+}
+
+/// Options for the initial state and the configuration of the database.
+#[derive(Debug, clap::Args)]
+struct DbOpt {
+  /// What to do when a disputed deposit is no longer available: reject the dispute, let
+  /// the available funds go negative, or hold what is available and record the rest as
+  /// debt.
+  #[clap(long, value_name = "reject|negative|debt", default_value_t)]
+  dispute_policy: DisputePolicy,
+
+  /// What to do with an amount that has more than 4 decimal places: reject the
+  /// transaction or round the amount (with banker's rounding, rounding half up or
+  /// truncating).
+  #[clap(long, value_name = "reject|half-even|half-up|truncate", default_value_t)]
+  precision_policy: PrecisionPolicy,
+
+  /// Reject deposits and withdrawals of a zero amount.
+  #[clap(long)]
+  reject_zero_amounts: bool,
+
+  /// Start from the state saved in a snapshot file instead of an empty state.
+  #[clap(long, value_name = "FILE")]
+  load_snapshot: Option<PathBuf>,
+
+  /// Seed accounts from an opening balances CSV file, as written by a previous run.
+  #[clap(long, value_name = "FILE")]
+  opening_balances: Option<PathBuf>,
+
+  /// Set credit limits from a CSV file with the columns client and limit (after seeding
+  /// the opening balances, if any).
+  #[clap(long, value_name = "FILE")]
+  credit_limits: Option<PathBuf>,
+
+  /// Limit withdrawals according to a velocity limits CSV file with the columns client,
+  /// daily_amount and hourly_count, where a row without a client sets the default limits.
+  /// Withdrawals must then have a timestamp column (in seconds since the Unix epoch).
+  #[clap(long, value_name = "FILE")]
+  velocity_limits: Option<PathBuf>,
+
+  /// Expire authorizations this many seconds after their timestamp column (in seconds
+  /// since the Unix epoch). Authorizations without a timestamp never expire.
+  #[clap(long, value_name = "SECONDS")]
+  authorization_ttl: Option<u64>,
+
+  /// Charge fees on withdrawals and chargebacks according to a fee schedule CSV file with
+  /// the columns type, fixed, percent, min and max.
+  #[clap(long, value_name = "FILE", requires = "fee_account")]
+  fees: Option<PathBuf>,
+
+  /// The client whose account the fees are credited to.
+  #[clap(long, value_name = "CLIENT", requires = "fees")]
+  fee_account: Option<u16>,
+
+  /// How to round fees to 4 decimal places.
+  #[clap(long, value_name = "half-even|half-up|truncate", default_value_t)]
+  fee_rounding: Rounding,
+}
+
+impl DbOpt {
+  /// Create the database, keeping a journal in every account if *journal* is set.
+  fn open(&self, journal: bool) -> Result<Db, Err> {
+    let db = match &self.load_snapshot {
+      Some(path) => load_snapshot(path)?,
+      None => Db::new(),
+    };
+
+    let mut db = db
+      .with_dispute_policy(self.dispute_policy)
+      .with_precision_policy(self.precision_policy)
+      .with_reject_zero_amounts(self.reject_zero_amounts)
+      .with_journal(journal);
+
+    if let (Some(path), Some(account)) = (&self.fees, self.fee_account) {
+      let fees =
+        FeeSchedule::new(ClientId::new(account)).with_rounding(self.fee_rounding);
+      db = db.with_fee_schedule(load_fee_schedule(fees, path)?);
+    }
+
+    if let Some(path) = &self.velocity_limits {
+      db = db.with_velocity_limits(load_velocity_limits(path)?);
+    }
+
+    if let Some(ttl) = self.authorization_ttl {
+      db = db.with_authorization_ttl(ttl);
+    }
+
+    if let Some(path) = &self.opening_balances {
+      load_opening_balances(&mut db, path)?;
+    }
+
+    if let Some(path) = &self.credit_limits {
+      load_credit_limits(&mut db, path)?;
+    }
+
+    Ok(db)
+  }
+
+  /// The configuration transactions are applied under, which a write-ahead log must be
+  /// replayed under too. Files are identified by a digest of their contents.
+  fn wal_config(&self) -> Result<WalConfig, Err> {
+    let digest = |path: &Option<PathBuf>| -> Result<String, Err> {
+      match path {
+        Some(path) => Ok(format!("sha256:{:x}", Sha256::digest(fs::read(path)?))),
+        None => Ok(String::from("none")),
+      }
+    };
+    let fee_account = self.fee_account.map_or(String::from("none"), |id| id.to_string());
+    let ttl = self.authorization_ttl.map_or(String::from("none"), |ttl| ttl.to_string());
 
-  /// Input CSV file.
-  #[clap(name = "FILE")]
-  file: PathBuf,
+    Ok(
+      WalConfig::new()
+        .with("dispute_policy", self.dispute_policy)
+        .with("precision_policy", self.precision_policy)
+        .with("reject_zero_amounts", self.reject_zero_amounts)
+        .with("opening_balances", digest(&self.opening_balances)?)
+        .with("credit_limits", digest(&self.credit_limits)?)
+        .with("velocity_limits", digest(&self.velocity_limits)?)
+        .with("authorization_ttl", ttl)
+        .with("fees", digest(&self.fees)?)
+        .with("fee_account", fee_account)
+        .with("fee_rounding", self.fee_rounding),
+    )
+  }
+}
+
+/// Options for the input files.
+#[derive(Debug, clap::Args)]
+struct InputOpt {
+  /// Merge the input files by a sequence number or timestamp column, instead of
+  /// processing them one after the other.
+  #[clap(long, value_name = "COLUMN")]
+  merge_by: Option<String>,
+
+  /// Format of the input files.
+  #[clap(long, value_enum, default_value_t = InputFormat::Csv)]
+  input_format: InputFormat,
+
+  /// Input files, or `-` for the standard input.
+  #[clap(name = "FILE", required = true)]
+  files: Vec<PathBuf>,
+}
+
+impl InputOpt {
+  fn open(&self) -> Result<Inputs, Err> {
+    Ok(Inputs {
+      inputs: self
+        .files
+        .iter()
+        .map(|path| Input::open(path, self.input_format))
+        .collect::<Result<_, _>>()?,
+      merge_by: self.merge_by.clone(),
+      current: 0,
+    })
+  }
 }
 
 #[derive(From, Display)]
-#[display(fmt = "{}")]
 enum Err {
   #[display(fmt = "IO Error: {}", _0)]
   Io(io::Error),
@@ -50,8 +330,49 @@ enum Err {
   #[display(fmt = "CSV Parsing Error: {}", _0)]
   Csv(csv::Error),
 
+  #[display(fmt = "JSON Error: {}", _0)]
+  Json(serde_json::Error),
+
   #[display(fmt = "Transaction Processing Error: {}", _0)]
   Tx(TxErr),
+
+  #[display(fmt = "Snapshot Error: {}", _0)]
+  Snapshot(SnapshotErr),
+
+  #[display(fmt = "Write-Ahead Log Error: {}", _0)]
+  Wal(WalErr),
+
+  #[display(fmt = "Opening Balance Error at line {}: {}", _0, _1)]
+  #[from(ignore)]
+  Opening(u64, TxErr),
+
+  #[display(fmt = "Credit Limit Error at line {}: {}", _0, _1)]
+  #[from(ignore)]
+  Limit(u64, TxErr),
+
+  #[display(fmt = "Velocity Limit Error at line {}: {}", _0, _1)]
+  #[from(ignore)]
+  Velocity(u64, TxErr),
+
+  #[display(fmt = "Fee Schedule Error at line {}: {}", _0, _1)]
+  #[from(ignore)]
+  Fees(u64, TxErr),
+
+  #[display(fmt = "Scale Error: {} does not fit in {} decimal places", _0, _1)]
+  #[from(ignore)]
+  Scale(Decimal, u32),
+
+  #[display(fmt = "Processing Aborted: {}", _0)]
+  #[from(ignore)]
+  Aborted(String),
+
+  #[display(fmt = "Validation Failed: {} problems found", _0)]
+  #[from(ignore)]
+  Invalid(u64),
+
+  #[display(fmt = "Hash Chain Verification Error: {}", _0)]
+  #[from(ignore)]
+  Chain(String),
 }
 
 impl fmt::Debug for Err {
@@ -60,16 +381,547 @@ impl fmt::Debug for Err {
   }
 }
 
-fn main() -> Result<(), Err> {
-  let opt = Opt::parse();
+/// An entry in a hash chain log.
+#[derive(Serialize, Deserialize, Debug)]
+struct ChainEntry {
+  line: u64,
+  tx: u32,
+  digest: Digest,
+}
 
-  if opt.license {
-    eprintln!("{}", LICENSE);
-    eprintln!();
-    eprintln!("{}", LICENSE_DEPS);
-    return Ok(());
+fn read_chain_log(path: &Path) -> Result<Vec<ChainEntry>, Err> {
+  let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(path)?;
+  let entries = reader.deserialize().collect::<Result<_, _>>()?;
+  Ok(entries)
+}
+
+/// Writer of rejected input rows.
+struct Rejects {
+  writer: csv::Writer<File>,
+  headers: csv::StringRecord,
+}
+
+impl Rejects {
+  fn create(path: &Path, headers: &csv::StringRecord) -> Result<Self, Err> {
+    let mut writer = csv::WriterBuilder::new().from_path(path)?;
+
+    let mut header = csv::StringRecord::new();
+    header.push_field("file");
+    header.push_field("line");
+    header.extend(headers);
+    header.push_field("code");
+    header.push_field("message");
+    header.push_field("velocity");
+    writer.write_record(&header)?;
+
+    Ok(Self { writer, headers: headers.clone() })
   }
 
+  /// Write a rejected row of *input*, where *record* is the row as read from the input
+  /// (if it could be read at all).
+  fn write(
+    &mut self,
+    input: &Input,
+    line: u64,
+    record: Option<&Record>,
+    code: &str,
+    message: &str,
+  ) -> Result<(), Err> {
+    let mut row = csv::StringRecord::new();
+    row.push_field(&input.name);
+    row.push_field(&line.to_string());
+
+    // Fit the original fields to the header by name, since the inputs are read flexibly
+    // and might not share the same columns.
+    for name in &self.headers {
+      let field = record.and_then(|record| record.field(&input.headers, name));
+      row.push_field(field.as_deref().unwrap_or_default());
+    }
+
+    row.push_field(code);
+    row.push_field(message);
+
+    // Flag velocity breaches, so they can be picked out for a fraud review.
+    let velocity = code == TxErrKind::VelocityExceeded.code();
+    row.push_field(&velocity.to_string());
+
+    self.writer.write_record(&row)?;
+
+    Ok(())
+  }
+}
+
+/// A stable, machine-readable code for a CSV error.
+fn csv_error_code(err: &csv::Error) -> &'static str {
+  match err.kind() {
+    csv::ErrorKind::Io(_) => "io_error",
+    csv::ErrorKind::Utf8 { .. } => "invalid_utf8",
+    csv::ErrorKind::UnequalLengths { .. } => "unequal_lengths",
+    csv::ErrorKind::Deserialize { .. } => "invalid_record",
+    _ => "csv_error",
+  }
+}
+
+/// The format of the input files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum InputFormat {
+  /// Comma-separated values with a header row.
+  Csv,
+  /// One JSON object per line, with amounts as strings or numbers.
+  Jsonl,
+}
+
+/// The format of the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+enum OutputFormat {
+  /// Comma-separated values with a header row.
+  Csv,
+  /// A single JSON array.
+  Json,
+  /// One JSON object per line.
+  Jsonl,
+}
+
+/// The fields of an input record, before they are deserialized into a transaction.
+enum Fields {
+  Csv(csv::StringRecord),
+  Json(serde_json::Value),
+}
+
+/// An input record along with its line number.
+struct Record {
+  line: u64,
+  fields: Fields,
+}
+
+/// An input record that could not be read, along with its line number.
+struct BadRecord {
+  line: u64,
+  code: &'static str,
+  message: String,
+}
+
+impl Record {
+  fn from_csv(record: csv::Result<csv::StringRecord>) -> Result<Self, BadRecord> {
+    match record {
+      Ok(record) => Ok(Self {
+        line: record.position().map_or(0, |pos| pos.line()),
+        fields: Fields::Csv(record),
+      }),
+      Err(e) => Err(BadRecord {
+        line: e.position().map_or(0, |pos| pos.line()),
+        code: csv_error_code(&e),
+        message: e.to_string(),
+      }),
+    }
+  }
+
+  fn from_json(line: u64, text: io::Result<String>) -> Result<Self, BadRecord> {
+    let bad = |code, message| BadRecord { line, code, message };
+    let text = text.map_err(|e| bad("io_error", e.to_string()))?;
+
+    match serde_json::from_str(&text) {
+      Ok(fields) => {
+        Ok(Self { line, fields: Fields::Json(serde_json::Value::Object(fields)) })
+      }
+      Err(e) => Err(bad("invalid_json", e.to_string())),
+    }
+  }
+
+  /// The value of the field called *name*, where *headers* are the CSV headers of the
+  /// input.
+  fn field(&self, headers: &csv::StringRecord, name: &str) -> Option<Cow<'_, str>> {
+    match &self.fields {
+      Fields::Csv(record) => {
+        let i = headers.iter().position(|header| header == name)?;
+        record.get(i).map(Cow::Borrowed)
+      }
+      Fields::Json(fields) => match fields.get(name)? {
+        serde_json::Value::Null => None,
+        serde_json::Value::String(value) => Some(Cow::Borrowed(value)),
+        value => Some(Cow::Owned(value.to_string())),
+      },
+    }
+  }
+
+  /// The record as a line of text.
+  fn text(&self) -> String {
+    match &self.fields {
+      Fields::Csv(record) => record.iter().collect::<Vec<_>>().join(","),
+      Fields::Json(fields) => fields.to_string(),
+    }
+  }
+
+  fn deserialize<T: DeserializeOwned>(
+    &self,
+    headers: &csv::StringRecord,
+  ) -> Result<T, BadRecord> {
+    let bad = |code, message| BadRecord { line: self.line, code, message };
+
+    match &self.fields {
+      Fields::Csv(record) => record
+        .deserialize(Some(headers))
+        .map_err(|e| bad(csv_error_code(&e), e.to_string())),
+      Fields::Json(fields) => {
+        // Amounts are read from strings, but a number is kept exactly as written too.
+        let mut fields = fields.clone();
+        if let Some(amount @ serde_json::Value::Number(_)) = fields.get_mut("amount") {
+          *amount = serde_json::Value::String(amount.to_string());
+        }
+
+        T::deserialize(&fields)
+          .map_err(|e: serde_json::Error| bad("invalid_record", e.to_string()))
+      }
+    }
+  }
+}
+
+/// An input file.
+struct Input {
+  name: String,
+  headers: csv::StringRecord,
+  records: Box<dyn Iterator<Item = Result<Record, BadRecord>>>,
+  next: Option<Result<Record, BadRecord>>,
+}
+
+impl Input {
+  fn open(path: &Path, format: InputFormat) -> Result<Self, Err> {
+    let (name, reader): (_, Box<dyn Read>) = if path == Path::new("-") {
+      (String::from("-"), Box::new(io::stdin()))
+    } else {
+      (path.display().to_string(), Box::new(File::open(path)?))
+    };
+
+    let (headers, records): (_, Box<dyn Iterator<Item = _>>) = match format {
+      InputFormat::Csv => {
+        let mut reader = csv::ReaderBuilder::new()
+          .flexible(true)
+          .trim(csv::Trim::All)
+          .from_reader(reader);
+        let headers = reader.headers()?.clone();
+        (headers, Box::new(reader.into_records().map(Record::from_csv)))
+      }
+      InputFormat::Jsonl => {
+        let records = BufReader::new(reader)
+          .lines()
+          .zip(1..)
+          .filter(|(text, _)| text.as_ref().map_or(true, |text| !text.trim().is_empty()))
+          .map(|(text, line)| Record::from_json(line, text));
+
+        // JSON records have no headers, report them with the transaction fields instead.
+        let headers = csv::StringRecord::from(TX_FIELDS.to_vec());
+        (headers, Box::new(records))
+      }
+    };
+
+    Ok(Self { name, headers, records, next: None })
+  }
+
+  /// Look at the next record without consuming it.
+  fn peek(&mut self) -> Option<&Result<Record, BadRecord>> {
+    if self.next.is_none() {
+      self.next = self.records.next();
+    }
+
+    self.next.as_ref()
+  }
+
+  /// The merge key of the next record, or `None` if it has none.
+  fn peek_key(&mut self, column: &str) -> Option<MergeKey> {
+    self.peek();
+
+    match &self.next {
+      Some(Ok(record)) => {
+        record.field(&self.headers, column).as_deref().and_then(MergeKey::parse)
+      }
+      _ => None,
+    }
+  }
+}
+
+/// The value of a merge column: numbers are compared numerically and anything else (e.g.
+/// ISO 8601 timestamps) lexicographically.
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+enum MergeKey {
+  Number(Decimal),
+  Text(String),
+}
+
+impl MergeKey {
+  fn parse(value: &str) -> Option<Self> {
+    if value.is_empty() {
+      None
+    } else if let Ok(number) = value.parse() {
+      Some(Self::Number(number))
+    } else {
+      Some(Self::Text(String::from(value)))
+    }
+  }
+}
+
+/// The input files, read either one after the other or merged by a column.
+struct Inputs {
+  inputs: Vec<Input>,
+  merge_by: Option<String>,
+  current: usize,
+}
+
+impl Inputs {
+  /// The index of the input holding the next record, if any.
+  ///
+  /// When merging, the next record is the one with the smallest key, where records
+  /// without a key (or that could not be read) come first and ties go to the first input.
+  fn next_input(&mut self) -> Option<usize> {
+    match &self.merge_by {
+      None => {
+        while self.current < self.inputs.len() {
+          if self.inputs[self.current].peek().is_some() {
+            return Some(self.current);
+          }
+
+          self.current += 1;
+        }
+
+        None
+      }
+      Some(column) => {
+        let mut next: Option<(usize, Option<MergeKey>)> = None;
+
+        for (i, input) in self.inputs.iter_mut().enumerate() {
+          if input.peek().is_none() {
+            continue;
+          }
+
+          let key = input.peek_key(column);
+
+          if next.as_ref().is_none_or(|(_, next_key)| key < *next_key) {
+            next = Some((i, key));
+          }
+        }
+
+        next.map(|(i, _)| i)
+      }
+    }
+  }
+
+  /// The next record, along with the index of its input.
+  fn next(&mut self) -> Option<(usize, Result<Record, BadRecord>)> {
+    let i = self.next_input()?;
+    self.inputs[i].next.take().map(|record| (i, record))
+  }
+}
+
+/// Limits on the number of rejected rows, beyond which processing is aborted.
+struct ErrorLimit {
+  strict: bool,
+  max: Option<u64>,
+  count: u64,
+}
+
+impl ErrorLimit {
+  /// Count a rejected row of *input*, where *record* is the row as read from the input
+  /// (if it could be read at all).
+  fn reject(
+    &mut self,
+    input: &Input,
+    line: u64,
+    record: Option<&Record>,
+    message: &str,
+  ) -> Result<(), Err> {
+    self.count += 1;
+
+    let position = match record {
+      Some(record) => {
+        format!("{}:{}: {}\n  {}", input.name, line, message, record.text())
+      }
+      None => format!("{}:{}: {}", input.name, line, message),
+    };
+
+    if self.strict {
+      return Err(Err::Aborted(position));
+    }
+
+    match self.max {
+      Some(max) if self.count > max => Err(Err::Aborted(format!(
+        "More than {} rows rejected, the last one at {}",
+        max, position
+      ))),
+      _ => Ok(()),
+    }
+  }
+}
+
+/// Write the accounts of *db* to the standard output in the given *format*, sorted by
+/// client ID, with their amounts rounded to *scale* decimal places. Fails without
+/// writing anything if an amount has too many digits for the scale.
+///
+/// The debt, credit used and dispute policy are only written when a dispute policy other
+/// than the default or a credit limit is in use. Every account gets the same columns, as
+/// the CSV header is taken from the first one.
+fn write_accounts(
+  db: &Db,
+  format: OutputFormat,
+  scale: u32,
+  rounding: Rounding,
+) -> Result<(), Err> {
+  let mut accounts: Vec<_> = db.accounts().collect();
+  accounts.sort_by_key(|account| account.id());
+  let policy = db.dispute_policy();
+  let debt = policy == DisputePolicy::HoldAvailable
+    || accounts.iter().any(|account| !account.debt().is_zero());
+  let credit = accounts.iter().any(|account| !account.credit_limit().is_zero());
+  let accounts: Vec<_> = accounts
+    .into_iter()
+    .map(|account| {
+      let mut rounded = account.rounded(scale, rounding);
+
+      if debt {
+        rounded = rounded.with_debt();
+      }
+
+      if credit {
+        rounded = rounded.with_credit_used();
+      }
+
+      if policy != DisputePolicy::default() {
+        rounded = rounded.with_dispute_policy(policy);
+      }
+
+      rounded
+    })
+    .collect();
+
+  // Nothing is written unless every amount gets exactly the requested scale.
+  for account in &accounts {
+    account.check().map_err(|value| Err::Scale(value, scale))?;
+  }
+
+  match format {
+    OutputFormat::Csv => {
+      let mut writer = csv::Writer::from_writer(io::stdout());
+
+      for account in accounts {
+        writer.serialize(account)?;
+      }
+
+      writer.flush()?;
+    }
+    OutputFormat::Json => {
+      let mut writer = BufWriter::new(io::stdout());
+      serde_json::to_writer_pretty(&mut writer, &accounts)?;
+      writeln!(writer)?;
+      writer.flush()?;
+    }
+    OutputFormat::Jsonl => {
+      let mut writer = BufWriter::new(io::stdout());
+
+      for account in accounts {
+        serde_json::to_writer(&mut writer, &account)?;
+        writeln!(writer)?;
+      }
+
+      writer.flush()?;
+    }
+  }
+
+  Ok(())
+}
+
+fn load_snapshot(path: &Path) -> Result<Db, Err> {
+  let snapshot = Snapshot::read(BufReader::new(File::open(path)?))?;
+  Ok(Db::restore(snapshot)?)
+}
+
+fn load_opening_balances(db: &mut Db, path: &Path) -> Result<(), Err> {
+  let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(path)?;
+  let headers = reader.headers()?.clone();
+
+  for record in reader.records() {
+    let record = record?;
+    let line = record.position().map_or(0, |pos| pos.line());
+    let balance: OpeningBalance = record.deserialize(Some(&headers))?;
+    db.open_account(&balance).map_err(|err| Err::Opening(line, err))?;
+  }
+
+  Ok(())
+}
+
+fn load_credit_limits(db: &mut Db, path: &Path) -> Result<(), Err> {
+  let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(path)?;
+  let headers = reader.headers()?.clone();
+
+  for record in reader.records() {
+    let record = record?;
+    let line = record.position().map_or(0, |pos| pos.line());
+    let limit: CreditLimit = record.deserialize(Some(&headers))?;
+    db.set_credit_limit(&limit).map_err(|err| Err::Limit(line, err))?;
+  }
+
+  Ok(())
+}
+
+fn load_fee_schedule(mut fees: FeeSchedule, path: &Path) -> Result<FeeSchedule, Err> {
+  let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(path)?;
+  let headers = reader.headers()?.clone();
+
+  for record in reader.records() {
+    let record = record?;
+    let line = record.position().map_or(0, |pos| pos.line());
+    let rule: FeeRule = record.deserialize(Some(&headers))?;
+    fees.add_rule(&rule).map_err(|err| Err::Fees(line, err))?;
+  }
+
+  Ok(fees)
+}
+
+fn load_velocity_limits(path: &Path) -> Result<VelocityLimits, Err> {
+  let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).from_path(path)?;
+  let headers = reader.headers()?.clone();
+  let mut limits = VelocityLimits::new();
+
+  for record in reader.records() {
+    let record = record?;
+    let line = record.position().map_or(0, |pos| pos.line());
+    let rule: VelocityRule = record.deserialize(Some(&headers))?;
+    limits.add_rule(&rule).map_err(|err| Err::Velocity(line, err))?;
+  }
+
+  Ok(limits)
+}
+
+/// Save a snapshot of *db*, replacing the file at *path* only once the snapshot is
+/// completely written.
+fn save_snapshot(db: &Db, path: &Path) -> Result<(), Err> {
+  // Append to the whole file name, so that the temporary file is never *path* itself.
+  let mut tmp_path = path.as_os_str().to_owned();
+  tmp_path.push(".tmp");
+  let tmp_path = PathBuf::from(tmp_path);
+
+  {
+    let mut writer = BufWriter::new(File::create(&tmp_path)?);
+    db.snapshot().write(&mut writer)?;
+    writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+  }
+
+  fs::rename(tmp_path, path)?;
+
+  // Make the rename itself durable.
+  #[cfg(unix)]
+  {
+    let dir = match path.parent() {
+      Some(dir) if !dir.as_os_str().is_empty() => dir,
+      _ => Path::new("."),
+    };
+
+    File::open(dir)?.sync_all()?;
+  }
+
+  Ok(())
+}
+
+fn main() -> Result<(), Err> {
+  let opt = Opt::parse();
+
   let log_level = match opt.verbose {
     0 => log::LevelFilter::Off,
     1 => log::LevelFilter::Error,
@@ -89,22 +941,161 @@ fn main() -> Result<(), Err> {
   debug!("Debug output enabled.");
   trace!("Trace output enabled.");
 
-  let input_file = File::open(opt.file)?;
-  let mut reader =
-    csv::ReaderBuilder::new().flexible(true).trim(csv::Trim::All).from_reader(input_file);
+  match opt.command {
+    Command::License => {
+      eprintln!("{}", LICENSE);
+      eprintln!();
+      eprintln!("{}", LICENSE_DEPS);
+      Ok(())
+    }
+    Command::Process(opt) => process(opt),
+    Command::Validate(opt) => validate(opt),
+  }
+}
+
+/// Check the input for problems without outputting any balances.
+fn validate(opt: ValidateOpt) -> Result<(), Err> {
+  let mut inputs = opt.input.open()?;
+  let mut db = opt.db.open(false)?;
+
+  let mut stdout = io::stdout();
+  let mut counts: BTreeMap<(&str, &str), u64> = BTreeMap::new();
+  let mut rows = 0;
+
+  while let Some((i, record)) = inputs.next() {
+    let input = &inputs.inputs[i];
+    rows += 1;
+
+    let problem = match record {
+      Err(bad) => Some((bad.line, "schema", bad.code, bad.message)),
+      Ok(record) => match record.deserialize::<Tx>(&input.headers) {
+        Err(bad) => Some((bad.line, "schema", bad.code, bad.message)),
+        Ok(tx) => match db.process(&tx) {
+          Ok(_) => None,
+          Err(err) => {
+            let category = match err.kind() {
+              TxErrKind::MissingAmount | TxErrKind::ExtraneousAmount => "schema",
+              _ => "semantic",
+            };
+
+            Some((record.line, category, err.code(), err.to_string()))
+          }
+        },
+      },
+    };
+
+    if let Some((line, category, code, message)) = problem {
+      writeln!(stdout, "{}:{}: {} {}: {}", input.name, line, category, code, message)?;
+      *counts.entry((category, code)).or_default() += 1;
+    }
+  }
+
+  let problems = counts.values().sum();
+  writeln!(stdout, "{} rows checked, {} problems found", rows, problems)?;
+
+  for ((category, code), count) in &counts {
+    writeln!(stdout, "  {} {}: {}", category, code, count)?;
+  }
+
+  if problems > 0 {
+    return Err(Err::Invalid(problems));
+  }
+
+  Ok(())
+}
+
+/// Process the input and output the balances of all accounts.
+fn process(opt: ProcessOpt) -> Result<(), Err> {
+  let mut inputs = opt.input.open()?;
+
+  let mut rejects = match &opt.rejects {
+    Some(path) => Some(Rejects::create(path, &inputs.inputs[0].headers)?),
+    None => None,
+  };
+
+  let mut chain_log = match &opt.chain_log {
+    Some(path) => Some(csv::Writer::from_path(path)?),
+    None => None,
+  };
+
+  let mut claimed_chain = match &opt.verify_chain {
+    Some(path) => Some(read_chain_log(path)?.into_iter()),
+    None => None,
+  };
+
+  let mut db = opt.db.open(opt.statements.is_some())?;
+
+  let applied = db.chain().len();
+
+  let (mut wal, records) = match &opt.wal {
+    Some(path) => {
+      let (wal, records) = Wal::open(path, &opt.db.wal_config()?)?;
+      let replayed = Wal::replay(&records, &mut db)?;
+      info!("Replayed {} of {} write-ahead log records", replayed, records.len());
+      (Some(wal), records)
+    }
+    None => (None, Vec::new()),
+  };
 
-  let mut db = Db::new();
+  // The rows of a resumed run that are already in the write-ahead log.
+  let covered = Wal::covered(&records, applied);
+
+  let mut error_limit = ErrorLimit { strict: opt.strict, max: opt.max_errors, count: 0 };
+  let (mut skipped, mut mismatched) = (0, 0);
+
+  'NEXT_TX: while let Some((i, record)) = inputs.next() {
+    let input = &inputs.inputs[i];
+
+    let line = match &record {
+      Ok(record) => record.line,
+      Err(bad) => bad.line,
+    };
+
+    let record = match record {
+      Ok(record) => record,
+      Err(bad) => {
+        error!("{}:{}: {}", input.name, bad.line, bad.message);
+
+        if let Some(rejects) = rejects.as_mut() {
+          rejects.write(input, bad.line, None, bad.code, &bad.message)?;
+        }
+
+        error_limit.reject(input, bad.line, None, &bad.message)?;
+        continue 'NEXT_TX;
+      }
+    };
 
-  'NEXT_TX: for tx in reader.deserialize() {
-    let tx = match tx {
+    let tx: Tx = match record.deserialize(&input.headers) {
       Ok(tx) => tx,
-      Err(e) => {
-        error!("{}", e);
+      Err(bad) => {
+        error!("{}:{}: {}", input.name, bad.line, bad.message);
+
+        if let Some(rejects) = rejects.as_mut() {
+          rejects.write(input, bad.line, Some(&record), bad.code, &bad.message)?;
+        }
+
+        error_limit.reject(input, bad.line, Some(&record), &bad.message)?;
         continue 'NEXT_TX;
       }
     };
 
-    debug!("CSV Transaction: {}", tx);
+    // Rows of a resumed run are only skipped if they are what was logged for them, since
+    // the input may have been replaced with new data under the same name.
+    if let Some(logged) = covered.get(&(input.name.as_str(), line)) {
+      if **logged == tx {
+        info!("{}:{}: Skipped, already in the write-ahead log", input.name, line);
+        skipped += 1;
+        continue 'NEXT_TX;
+      }
+
+      warn!(
+        "{}:{}: Does not match the write-ahead log ({}), processed as a new row",
+        input.name, line, logged
+      );
+      mismatched += 1;
+    }
+
+    debug!("Transaction: {}", tx);
 
     match db.process(&tx) {
       Ok(_) => {}
@@ -112,24 +1103,204 @@ fn main() -> Result<(), Err> {
         error!("Error: Transaction skipped: {}", tx);
         error!("  Reason: {}", err);
 
-        if let Some(account) = db.get_account(ClientId::new(tx.client)) {
-          error!("  Related Account: {}", account)
+        if let (Some(available), Some(held)) = (err.available(), err.held()) {
+          error!("  Account Balances: Available={} Held={}", available, held);
+        }
+
+        if let Some(rejects) = rejects.as_mut() {
+          rejects.write(input, line, Some(&record), err.code(), &err.to_string())?;
+        }
+
+        error_limit.reject(input, line, Some(&record), &err.to_string())?;
+        continue 'NEXT_TX;
+      }
+    }
+
+    if let Some(wal) = wal.as_mut() {
+      let input = input.name.clone();
+      wal.append(&WalRecord { seq: db.chain().len(), input, line, tx: tx.clone() })?;
+    }
+
+    let entry = ChainEntry { line, tx: tx.tx, digest: db.chain().head() };
+
+    if let Some(chain_log) = chain_log.as_mut() {
+      chain_log.serialize(&entry)?;
+    }
+
+    if let Some(claimed_chain) = claimed_chain.as_mut() {
+      match claimed_chain.next() {
+        Some(claimed) if claimed.tx == entry.tx && claimed.digest == entry.digest => {}
+        Some(claimed) => {
+          return Err(Err::Chain(format!(
+            "Chains diverge at line {} (Tx={}): expected Tx={} with digest {}, got {}",
+            line, entry.tx, claimed.tx, claimed.digest, entry.digest
+          )))
+        }
+        None => {
+          return Err(Err::Chain(format!(
+            "Chains diverge at line {} (Tx={}): transaction is not in the claimed chain",
+            line, entry.tx
+          )))
         }
       }
     }
   }
 
-  let mut writer = csv::Writer::from_writer(io::stdout());
+  if !covered.is_empty() {
+    warn!(
+      "Resumed from the write-ahead log: {} rows skipped, {} rows did not match it",
+      skipped, mismatched
+    );
+  }
+
+  if let Some(rejects) = rejects.as_mut() {
+    rejects.writer.flush()?;
+  }
+
+  if let Some(chain_log) = chain_log.as_mut() {
+    chain_log.flush()?;
+  }
+
+  if let Some(mut claimed_chain) = claimed_chain {
+    if let Some(claimed) = claimed_chain.next() {
+      return Err(Err::Chain(format!(
+        "Chains diverge after the end of the input: claimed chain continues with Tx={} \
+         (line {})",
+        claimed.tx, claimed.line
+      )));
+    }
+
+    eprintln!("Hash chain verified: head={}", db.chain().head());
+    return Ok(());
+  }
+
+  if let Some(claimed) = opt.verify_head {
+    if claimed != db.chain().head() {
+      return Err(Err::Chain(format!(
+        "Heads diverge after {} transactions: expected {}, got {}",
+        db.chain().len(),
+        claimed,
+        db.chain().head()
+      )));
+    }
 
-  for account in db.accounts() {
-    writer.serialize(account)?;
+    eprintln!("Hash chain verified: head={}", db.chain().head());
+    return Ok(());
   }
 
-  for account in db.accounts_locked() {
-    writer.serialize(account)?;
+  if let Some(path) = &opt.save_snapshot {
+    save_snapshot(&db, path)?;
+
+    // The snapshot covers the whole log, which would otherwise grow without bound.
+    if let Some(wal) = wal.as_mut() {
+      wal.truncate()?;
+    }
   }
 
-  writer.flush()?;
+  write_accounts(&db, opt.output_format, opt.scale, opt.rounding)?;
+
+  if opt.head {
+    eprintln!("head={}", db.chain().head());
+  }
+
+  if let Some(dir) = &opt.statements {
+    for account in db.accounts() {
+      let path = dir.join(format!("client-{}.csv", account.id().value()));
+      let mut writer = csv::Writer::from_path(path)?;
+
+      for entry in account.journal().unwrap_or_default() {
+        writer.serialize(entry)?;
+      }
+
+      writer.flush()?;
+    }
+  }
 
   Ok(())
 }
+
+#[cfg(test)]
+mod main_tests {
+  use crate::{Input, InputFormat, Inputs};
+  use std::fs;
+  use std::path::PathBuf;
+
+  /// Write a CSV input file called *name* for test *test*.
+  fn input(test: &str, name: &str, contents: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!(
+      "tx-engine-{}-{}-{}",
+      std::process::id(),
+      test,
+      name
+    ));
+    fs::write(&path, contents).unwrap();
+    path
+  }
+
+  /// The transaction IDs of all rows of *paths*, in the order they are read.
+  fn order(paths: &[PathBuf], merge_by: Option<&str>) -> Vec<String> {
+    let mut inputs = Inputs {
+      inputs: paths
+        .iter()
+        .map(|path| Input::open(path, InputFormat::Csv).unwrap())
+        .collect(),
+      merge_by: merge_by.map(String::from),
+      current: 0,
+    };
+
+    let mut order = Vec::new();
+
+    while let Some((i, record)) = inputs.next() {
+      let field = record.ok().and_then(|record| {
+        record.field(&inputs.inputs[i].headers, "tx").map(|tx| tx.into_owned())
+      });
+      order.push(field.unwrap());
+    }
+
+    for path in paths {
+      fs::remove_file(path).unwrap();
+    }
+
+    order
+  }
+
+  #[test]
+  fn merge() {
+    let a = "type,client,tx,amount,seq\n\
+             deposit,1,1,1,1\n\
+             deposit,1,3,1,3\n\
+             deposit,1,5,1,5\n";
+    let b = "type,client,tx,amount,seq\n\
+             deposit,1,2,1,2\n\
+             deposit,1,4,1,3\n\
+             deposit,1,6,1,10\n";
+
+    // One file after the other by default.
+    let paths = [input("merge", "a1", a), input("merge", "b1", b)];
+    assert_eq!(order(&paths, None), ["1", "3", "5", "2", "4", "6"]);
+
+    // Keys are compared as numbers, and the tie at 3 goes to the file given first.
+    let paths = [input("merge", "a2", a), input("merge", "b2", b)];
+    assert_eq!(order(&paths, Some("seq")), ["1", "2", "3", "4", "5", "6"]);
+    let paths = [input("merge", "b3", b), input("merge", "a3", a)];
+    assert_eq!(order(&paths, Some("seq")), ["1", "2", "4", "3", "5", "6"]);
+  }
+
+  #[test]
+  fn merge_missing_column() {
+    let a = "type,client,tx,amount,seq\n\
+             deposit,1,1,1,1\n\
+             deposit,1,2,1,2\n";
+    let c = "type,client,tx,amount\n\
+             deposit,1,3,1\n\
+             deposit,1,4,1\n";
+
+    // Rows without a key are processed as soon as they are reached.
+    let paths = [input("missing", "a1", a), input("missing", "c1", c)];
+    assert_eq!(order(&paths, Some("seq")), ["3", "4", "1", "2"]);
+
+    // Without the column in any file, the files are read one after the other.
+    let paths = [input("missing", "a2", a), input("missing", "c2", c)];
+    assert_eq!(order(&paths, Some("timestamp")), ["1", "2", "3", "4"]);
+  }
+}
@@ -0,0 +1,56 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+/// An opening balance of an account, as found in the output of a previous run.
+///
+/// Opening balances seed a [database](crate::Db) without replaying the history that led
+/// to them.
+///
+/// # Errors
+///
+/// * An error is thrown if the account already exists.
+///
+/// * An error is thrown if the held funds, the authorized funds or the debt are negative.
+///
+/// * An error is thrown if the total does not match the sum of the available, held and
+///   authorized funds.
+///
+/// * An error is thrown if there are authorized funds: the authorizations behind them are
+///   not known, so they could neither be captured nor voided. Use
+///   [snapshots](crate::Snapshot) to carry over pending authorizations.
+///
+/// # Notes
+///
+/// * The disputed transactions behind carried-over held funds are not known, so
+///   carried-over held funds are kept separately from the held funds of disputes on
+///   transactions processed afterwards (see [Account::carried_held](crate::Account)).
+///   They cannot be resolved or charged back, but an operator can
+///   [release](crate::Release) them. Use [snapshots](crate::Snapshot) to carry over the
+///   disputes themselves.
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct OpeningBalance {
+  pub client: u16,
+  pub available: Decimal,
+  pub held: Decimal,
+  #[serde(default)]
+  pub authorized: Decimal,
+  pub total: Decimal,
+  pub locked: bool,
+  #[serde(default)]
+  pub debt: Decimal,
+}
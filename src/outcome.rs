@@ -0,0 +1,206 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{ClientId, TxErr, TxId, TxType};
+use derive_more::Display;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+/// The state of a deposit or withdrawal.
+#[derive(Debug, Display, PartialEq, Eq, Clone, Copy, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TxState {
+  Released,
+  Held,
+  Reversed,
+}
+
+/// A change in the state of a deposit or withdrawal, where *from* is `None` if the
+/// transaction was just created.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct Transition {
+  pub from: Option<TxState>,
+  pub to: TxState,
+}
+
+/// The effect of a transaction (or an authorization expiry) on one account.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize)]
+pub struct Effect {
+  client: ClientId,
+  created: bool,
+  before: (Decimal, Decimal, Decimal),
+  after: (Decimal, Decimal, Decimal),
+  transition: Option<Transition>,
+  locked: bool,
+  unlocked: bool,
+}
+
+impl Effect {
+  pub(crate) fn new(
+    client: ClientId,
+    created: bool,
+    before: (Decimal, Decimal, Decimal),
+    after: (Decimal, Decimal, Decimal),
+    transition: Option<Transition>,
+    locked: (bool, bool),
+  ) -> Self {
+    Self {
+      client,
+      created,
+      before,
+      after,
+      transition,
+      locked: !locked.0 && locked.1,
+      unlocked: locked.0 && !locked.1,
+    }
+  }
+
+  /// Get the client id.
+  pub fn client(&self) -> ClientId {
+    self.client
+  }
+
+  /// Whether the account was created by the transaction.
+  pub fn created(&self) -> bool {
+    self.created
+  }
+
+  /// Get the account's balances before the transaction, as `(available, held,
+  /// authorized)`.
+  pub fn before(&self) -> (Decimal, Decimal, Decimal) {
+    self.before
+  }
+
+  /// Get the account's balances after the transaction, as `(available, held,
+  /// authorized)`.
+  pub fn after(&self) -> (Decimal, Decimal, Decimal) {
+    self.after
+  }
+
+  /// Get the change in the state of the deposit or withdrawal the transaction created or
+  /// refers to in the account, if any.
+  pub fn transition(&self) -> Option<Transition> {
+    self.transition
+  }
+
+  /// Whether the account was locked by the transaction.
+  pub fn locked(&self) -> bool {
+    self.locked
+  }
+
+  /// Whether the account was unlocked by the transaction.
+  pub fn unlocked(&self) -> bool {
+    self.unlocked
+  }
+}
+
+/// The effect of a successfully applied transaction, or of an expired authorization.
+///
+/// The transaction's own client is always affected, and the other party of a transfer
+/// and the account collecting a fee can be too. The methods that do not take a client
+/// describe the effect on the transaction's own client.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize)]
+pub struct Outcome {
+  tx: TxId,
+  #[serde(rename = "type")]
+  typ: TxType,
+  effects: Vec<Effect>,
+}
+
+impl Outcome {
+  /// Create an outcome, where the first of *effects* is the one on the transaction's own
+  /// client.
+  pub(crate) fn new(tx: TxId, typ: TxType, effects: Vec<Effect>) -> Self {
+    assert!(!effects.is_empty());
+    Self { tx, typ, effects }
+  }
+
+  /// Get the transaction id.
+  pub fn tx(&self) -> TxId {
+    self.tx
+  }
+
+  /// Get the transaction type.
+  pub fn typ(&self) -> TxType {
+    self.typ
+  }
+
+  /// Get the effects on every affected account, starting with the transaction's own
+  /// client.
+  pub fn effects(&self) -> &[Effect] {
+    &self.effects
+  }
+
+  /// Get the effect on the account of *client*, if it was affected.
+  pub fn effect(&self, client: ClientId) -> Option<&Effect> {
+    self.effects.iter().find(|effect| effect.client == client)
+  }
+
+  /// Get the client id.
+  pub fn client(&self) -> ClientId {
+    self.effects[0].client()
+  }
+
+  /// Whether the account was created by the transaction.
+  pub fn created(&self) -> bool {
+    self.effects[0].created()
+  }
+
+  /// Get the account's balances before the transaction, as `(available, held,
+  /// authorized)`.
+  pub fn before(&self) -> (Decimal, Decimal, Decimal) {
+    self.effects[0].before()
+  }
+
+  /// Get the account's balances after the transaction, as `(available, held,
+  /// authorized)`.
+  pub fn after(&self) -> (Decimal, Decimal, Decimal) {
+    self.effects[0].after()
+  }
+
+  /// Get the change in the state of the deposit or withdrawal the transaction created or
+  /// refers to, if any.
+  pub fn transition(&self) -> Option<Transition> {
+    self.effects[0].transition()
+  }
+
+  /// Whether the account was locked by the transaction.
+  pub fn locked(&self) -> bool {
+    self.effects[0].locked()
+  }
+
+  /// Whether the account was unlocked by the transaction.
+  pub fn unlocked(&self) -> bool {
+    self.effects[0].unlocked()
+  }
+}
+
+/// An observer of the transactions processed by a [database](crate::Db).
+///
+/// All methods do nothing by default.
+pub trait Observer {
+  /// Called after a transaction is successfully applied.
+  fn applied(&mut self, _outcome: &Outcome) {}
+
+  /// Called after an authorization expired, with an outcome that has the ID and type of
+  /// the authorization and the release of its funds. Expiries are not transactions, but
+  /// follow from the timestamps of the transactions that are applied, so this is called
+  /// before [applied](Observer::applied) for the transaction that advanced the clock, or
+  /// right after it if the authorization itself was already expired.
+  fn expired(&mut self, _outcome: &Outcome) {}
+
+  /// Called after a transaction is rejected.
+  fn rejected(&mut self, _err: &TxErr) {}
+}
@@ -0,0 +1,132 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{Rounding, TxErrKind};
+use derive_more::Display;
+use rust_decimal::Decimal;
+use serde::{Serialize, Serializer};
+use std::str::FromStr;
+
+/// The maximum number of decimal places of an amount.
+pub const MAX_SCALE: u32 = 4;
+
+/// What to do when a deposit is disputed but the client does not have enough available
+/// funds to cover the disputed amount (e.g. because the deposit was since withdrawn).
+#[derive(Debug, Display, PartialEq, Eq, Clone, Copy, Default)]
+pub enum DisputePolicy {
+  /// Reject the dispute with an [Insufficient](crate::TxErrKind::Insufficient) error.
+  #[default]
+  #[display(fmt = "reject")]
+  Reject,
+
+  /// Hold the whole disputed amount, letting the available funds go negative.
+  #[display(fmt = "negative")]
+  AllowNegative,
+
+  /// Hold whatever is available and record the rest of the disputed amount as debt.
+  #[display(fmt = "debt")]
+  HoldAvailable,
+}
+
+impl Serialize for DisputePolicy {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(self)
+  }
+}
+
+impl FromStr for DisputePolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "reject" => Ok(Self::Reject),
+      "negative" => Ok(Self::AllowNegative),
+      "debt" => Ok(Self::HoldAvailable),
+      _ => {
+        Err(format!("Unknown dispute policy `{}` (expected reject, negative or debt)", s))
+      }
+    }
+  }
+}
+
+/// What to do with an amount that has more than [MAX_SCALE] decimal places.
+#[derive(Debug, Display, PartialEq, Eq, Clone, Copy, Default)]
+pub enum PrecisionPolicy {
+  /// Reject the transaction with an [excessive
+  /// precision](crate::TxErrKind::ExcessivePrecision) error.
+  #[default]
+  #[display(fmt = "reject")]
+  Reject,
+
+  /// Round the amount to [MAX_SCALE] decimal places.
+  #[display(fmt = "{}", _0)]
+  Round(Rounding),
+}
+
+impl PrecisionPolicy {
+  /// Apply the policy to *amount*.
+  pub fn apply(self, amount: Decimal) -> Result<Decimal, TxErrKind> {
+    if amount.normalize().scale() <= MAX_SCALE {
+      return Ok(amount);
+    }
+
+    match self {
+      PrecisionPolicy::Reject => Err(TxErrKind::ExcessivePrecision),
+      PrecisionPolicy::Round(rounding) => Ok(rounding.round(amount, MAX_SCALE)),
+    }
+  }
+}
+
+impl FromStr for PrecisionPolicy {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "reject" => Ok(Self::Reject),
+      _ => s.parse().map(Self::Round).map_err(|_| {
+        let expected = "reject, half-even, half-up or truncate";
+        format!("Unknown precision policy `{}` (expected {})", s, expected)
+      }),
+    }
+  }
+}
+
+#[cfg(test)]
+mod policy_tests {
+  use crate::{PrecisionPolicy, Rounding, TxErrKind};
+  use rust_decimal::Decimal;
+
+  #[test]
+  fn precision() {
+    let apply = |policy: PrecisionPolicy, value: &str| {
+      policy.apply(value.parse::<Decimal>().unwrap()).map(|amount| amount.to_string())
+    };
+
+    let round = PrecisionPolicy::Round;
+    assert_eq!(apply(PrecisionPolicy::Reject, "1.1234"), Ok(String::from("1.1234")));
+    assert_eq!(apply(PrecisionPolicy::Reject, "1.123400"), Ok(String::from("1.123400")));
+    assert_eq!(
+      apply(PrecisionPolicy::Reject, "1.12345"),
+      Err(TxErrKind::ExcessivePrecision)
+    );
+    assert_eq!(apply(round(Rounding::HalfEven), "1.12345"), Ok(String::from("1.1234")));
+    assert_eq!(apply(round(Rounding::HalfUp), "1.12345"), Ok(String::from("1.1235")));
+    assert_eq!(apply(round(Rounding::Truncate), "1.12349"), Ok(String::from("1.1234")));
+
+    assert_eq!("reject".parse(), Ok(PrecisionPolicy::Reject));
+    assert_eq!("half-up".parse(), Ok(round(Rounding::HalfUp)));
+    assert!("half-down".parse::<PrecisionPolicy>().is_err());
+  }
+}
@@ -0,0 +1,96 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{ClientId, TxErrKind, TxId};
+use derive_more::Display;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// A release is an administrative return of held funds carried over from an [opening
+/// balance](crate::OpeningBalance) to the available funds.
+///
+/// The disputes behind carried-over held funds are not known, so these funds cannot be
+/// resolved or charged back. A release lets an operator settle them once the disputes
+/// have been settled elsewhere.
+///
+/// # Errors
+///
+/// * An error is thrown if the [client ID](ClientId) and account do not already exist.
+///
+/// * An error is thrown if the [transaction ID](TxId) has already been used.
+///
+/// * An error is thrown if the operator or the reason is missing.
+///
+/// * An error is thrown if the amount is negative, zero, or more than the carried-over
+///   held funds.
+///
+/// # Notes
+///
+/// * A release without an amount releases all of the carried-over held funds.
+///
+/// * A release also applies to a locked account, which stays locked.
+#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+#[display(fmt = "Release {} {} Operator={} Reason={}", id, client, operator, reason)]
+pub struct Release {
+  id: TxId,
+  client: ClientId,
+  amount: Option<Decimal>,
+  operator: String,
+  reason: String,
+}
+
+impl Release {
+  pub fn new(
+    id: TxId,
+    client: ClientId,
+    amount: Option<Decimal>,
+    operator: String,
+    reason: String,
+  ) -> Result<Self, TxErrKind> {
+    if operator.is_empty() || reason.is_empty() {
+      return Err(TxErrKind::MissingAdminDetails);
+    }
+
+    match amount {
+      Some(amount) if amount.is_sign_negative() => Err(TxErrKind::NegativeAmount),
+      _ => Ok(Self { id, client, amount, operator, reason }),
+    }
+  }
+
+  /// Get the release's id.
+  pub fn id(&self) -> TxId {
+    self.id
+  }
+
+  /// Get the release's client.
+  pub fn client(&self) -> ClientId {
+    self.client
+  }
+
+  /// Get the released amount, if only part of the carried-over held funds is released.
+  pub fn amount(&self) -> Option<Decimal> {
+    self.amount
+  }
+
+  /// Get who released the funds.
+  pub fn operator(&self) -> &str {
+    &self.operator
+  }
+
+  /// Get why the funds were released.
+  pub fn reason(&self) -> &str {
+    &self.reason
+  }
+}
@@ -19,7 +19,11 @@ use derive_new::new;
 
 /// A resolve is a resolution of a dispute.
 ///
-/// A resolve must increase the available funds and decrease held funds.
+/// A resolve of a disputed deposit must increase the available funds and decrease held
+/// funds.
+///
+/// A resolve of a disputed withdrawal must decrease held funds, leaving the withdrawal in
+/// place.
 ///
 /// # Errors
 ///
@@ -28,7 +32,7 @@ use derive_new::new;
 /// * An error is thrown if the [transaction ID](TxId) does not already exist.
 ///
 /// * An error is thrown if the [transaction ID](TxId) does not refer to a held deposit
-///   transaction associated with [the corresponding client](ClientId).
+///   or withdrawal transaction associated with [the corresponding client](ClientId).
 ///
 /// # Notes
 ///
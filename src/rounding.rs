@@ -0,0 +1,117 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use derive_more::Display;
+use rust_decimal::{Decimal, RoundingStrategy};
+use std::str::FromStr;
+
+/// How to round an amount to a fixed number of decimal places.
+#[derive(Debug, Display, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Rounding {
+  /// Round to the nearest value, with midpoints going to the even neighbor (i.e. banker's
+  /// rounding).
+  #[default]
+  #[display(fmt = "half-even")]
+  HalfEven,
+
+  /// Round to the nearest value, with midpoints going away from zero.
+  #[display(fmt = "half-up")]
+  HalfUp,
+
+  /// Drop the extra decimal places, i.e. round towards zero.
+  #[display(fmt = "truncate")]
+  Truncate,
+}
+
+impl Rounding {
+  /// Round *value* to *scale* decimal places, padding it with zeros if needed.
+  ///
+  /// A value with too many digits to fit is padded with as many zeros as it can take,
+  /// see [Rounding::round_exact] to get exactly *scale* decimal places instead.
+  pub fn round(self, value: Decimal, scale: u32) -> Decimal {
+    let strategy = match self {
+      Rounding::HalfEven => RoundingStrategy::MidpointNearestEven,
+      Rounding::HalfUp => RoundingStrategy::MidpointAwayFromZero,
+      Rounding::Truncate => RoundingStrategy::ToZero,
+    };
+
+    let mut value = value.round_dp_with_strategy(scale, strategy);
+    value.rescale(scale);
+
+    // Avoid printing e.g. `-0.0000` for a small negative value rounded to zero.
+    if value.is_zero() {
+      value.set_sign_positive(true);
+    }
+
+    value
+  }
+
+  /// Round *value* to exactly *scale* decimal places, padding it with zeros if needed.
+  ///
+  /// Returns `None` if the value has too many digits to be written with *scale* decimal
+  /// places.
+  pub fn round_exact(self, value: Decimal, scale: u32) -> Option<Decimal> {
+    Some(self.round(value, scale)).filter(|value| value.scale() == scale)
+  }
+}
+
+impl FromStr for Rounding {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "half-even" => Ok(Self::HalfEven),
+      "half-up" => Ok(Self::HalfUp),
+      "truncate" => Ok(Self::Truncate),
+      _ => Err(format!(
+        "Unknown rounding mode `{}` (expected half-even, half-up or truncate)",
+        s
+      )),
+    }
+  }
+}
+
+#[cfg(test)]
+mod rounding_tests {
+  use crate::Rounding;
+  use rust_decimal::Decimal;
+
+  #[test]
+  fn round() {
+    let round = |rounding: Rounding, value: &str, scale| {
+      rounding.round(value.parse::<Decimal>().unwrap(), scale).to_string()
+    };
+
+    assert_eq!(round(Rounding::HalfEven, "1.5", 4), "1.5000");
+    assert_eq!(round(Rounding::HalfEven, "0", 4), "0.0000");
+    assert_eq!(round(Rounding::HalfEven, "0.00025", 4), "0.0002");
+    assert_eq!(round(Rounding::HalfEven, "0.00035", 4), "0.0004");
+    assert_eq!(round(Rounding::HalfUp, "0.00025", 4), "0.0003");
+    assert_eq!(round(Rounding::HalfUp, "-0.00025", 4), "-0.0003");
+    assert_eq!(round(Rounding::Truncate, "0.00029", 4), "0.0002");
+    assert_eq!(round(Rounding::Truncate, "-0.00009", 4), "0.0000");
+    assert_eq!(round(Rounding::HalfEven, "2.5", 0), "2");
+  }
+
+  #[test]
+  fn round_exact() {
+    let value = "9.25".parse::<Decimal>().unwrap();
+    let rounded = Rounding::HalfEven.round_exact(value, 27).unwrap();
+    assert_eq!(rounded.to_string(), "9.250000000000000000000000000");
+    assert_eq!(Rounding::HalfEven.round_exact(value, 28), None);
+    assert_eq!(Rounding::HalfEven.round_exact(Decimal::MAX, 1), None);
+    assert_eq!(Rounding::HalfEven.round_exact(Decimal::MAX, 0), Some(Decimal::MAX));
+  }
+}
@@ -0,0 +1,87 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::account::AccountSnapshot;
+use crate::velocity::Withdrawal;
+use crate::{Chain, Transfer, TxId};
+use derive_more::{Display, From};
+use serde::{Deserialize, Serialize};
+use std::io::{self, Read, Write};
+
+/// The version of the snapshot format written by this library.
+pub const SNAPSHOT_VERSION: u32 = 1;
+
+#[derive(Display, Debug, From)]
+pub enum SnapshotErr {
+  #[display(fmt = "IO Error: {}", _0)]
+  Io(io::Error),
+
+  #[display(fmt = "Invalid snapshot: {}", _0)]
+  Json(serde_json::Error),
+
+  #[display(fmt = "Unsupported snapshot version {} (expected {})", _0, SNAPSHOT_VERSION)]
+  #[from(ignore)]
+  Version(u32),
+
+  #[display(fmt = "Invalid snapshot: {}", _0)]
+  #[from(ignore)]
+  Invalid(String),
+}
+
+impl std::error::Error for SnapshotErr {}
+
+/// A versioned snapshot of the complete state of a [database](crate::Db).
+///
+/// A snapshot covers all accounts (locked or not) along with their deposits and
+/// withdrawals in every state, all used transaction IDs, all transfers, the withdrawals
+/// counted towards velocity limits, the [clock](crate::Db::clock) and the hash chain. It
+/// does not cover the database's configuration (e.g. its dispute policy).
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Snapshot {
+  pub(crate) version: u32,
+  pub(crate) accounts: Vec<AccountSnapshot>,
+  pub(crate) tx_ids: Vec<TxId>,
+  #[serde(default)]
+  pub(crate) transfers: Vec<Transfer>,
+  #[serde(default)]
+  pub(crate) withdrawals: Vec<Withdrawal>,
+  #[serde(default)]
+  pub(crate) clock: Option<u64>,
+  pub(crate) chain: Chain,
+}
+
+impl Snapshot {
+  /// Get the snapshot's format version.
+  pub fn version(&self) -> u32 {
+    self.version
+  }
+
+  /// Write the snapshot as JSON.
+  pub fn write<W: Write>(&self, writer: W) -> Result<(), SnapshotErr> {
+    serde_json::to_writer(writer, self)?;
+    Ok(())
+  }
+
+  /// Read a snapshot from JSON, checking its format version.
+  pub fn read<R: Read>(reader: R) -> Result<Self, SnapshotErr> {
+    let snapshot: Snapshot = serde_json::from_reader(reader)?;
+
+    if snapshot.version != SNAPSHOT_VERSION {
+      return Err(SnapshotErr::Version(snapshot.version));
+    }
+
+    Ok(snapshot)
+  }
+}
@@ -0,0 +1,104 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{ClientId, TxErrKind, TxId};
+use derive_more::Display;
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// A transfer is a movement of funds from one client's account to another's.
+///
+/// A transfer must decrease the available funds of the source account and increase the
+/// available funds of the destination account by the same amount, or leave both
+/// untouched. The source account keeps it as a withdrawal and the destination account as
+/// a deposit, both with the transfer's transaction ID.
+///
+/// A dispute or resolve of a transfer, by either party, applies to the deposit in the
+/// destination account only. A chargeback reverses that deposit, credits the source
+/// account back and locks the destination account.
+///
+/// # Errors
+///
+/// * An error is thrown if the source [client ID](ClientId) and account do not already
+///   exist.
+///
+/// * An error is thrown if the [transaction ID](TxId) has already been used.
+///
+/// * An error is thrown if the source and destination accounts are the same.
+///
+/// * An error is thrown if either account is locked.
+///
+/// * An error is thrown if the source account does not have enough available funds, or if
+///   the destination account would overflow.
+///
+/// # Notes
+///
+/// * The destination account is created if it does not already exist, as with a deposit.
+#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
+#[display(fmt = "Transfer {} From={} To={} Amount={}", id, source, destination, amount)]
+pub struct Transfer {
+  id: TxId,
+  source: ClientId,
+  destination: ClientId,
+  amount: Decimal,
+}
+
+impl Transfer {
+  pub fn new(
+    id: TxId,
+    source: ClientId,
+    destination: ClientId,
+    amount: Decimal,
+  ) -> Result<Self, TxErrKind> {
+    if amount.is_sign_negative() {
+      Err(TxErrKind::NegativeAmount)
+    } else if source == destination {
+      Err(TxErrKind::SelfTransfer)
+    } else {
+      Ok(Self { id, source, destination, amount })
+    }
+  }
+
+  /// Get the transfer's id.
+  pub fn id(&self) -> TxId {
+    self.id
+  }
+
+  /// Get the client the funds are transferred from.
+  pub fn source(&self) -> ClientId {
+    self.source
+  }
+
+  /// Get the client the funds are transferred to.
+  pub fn destination(&self) -> ClientId {
+    self.destination
+  }
+
+  /// Get the transfer's amount.
+  pub fn amount(&self) -> Decimal {
+    self.amount
+  }
+
+  /// Get the other party of the transfer, if *client* is one of its parties.
+  pub fn counterparty(&self, client: ClientId) -> Option<ClientId> {
+    if client == self.source {
+      Some(self.destination)
+    } else if client == self.destination {
+      Some(self.source)
+    } else {
+      None
+    }
+  }
+}
@@ -13,11 +13,15 @@
 
 #![warn(clippy::all)]
 
+use crate::{ClientId, TxErrKind, TxId};
 use derive_more::Display;
 use rust_decimal::Decimal;
-use serde::{Deserialize, Serialize};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::convert::TryFrom;
+use std::fmt;
 
-#[derive(Serialize, Deserialize, Debug, Clone, Copy, Display)]
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Hash, Clone, Copy, Display)]
 #[serde(rename_all = "lowercase")]
 pub enum TxType {
   Deposit,
@@ -25,9 +29,20 @@ pub enum TxType {
   Dispute,
   Resolve,
   Chargeback,
+  Unlock,
+  Transfer,
+  Limit,
+  Authorize,
+  Capture,
+  Void,
+  Release,
 }
 
-#[derive(Serialize, Deserialize, Debug, Display, Clone, Copy)]
+/// A raw transaction record, as found in the input.
+///
+/// See [Transaction] for a typed version that only holds the fields that make sense for
+/// each type of transaction.
+#[derive(Serialize, Deserialize, Debug, Display, PartialEq, Eq, Clone)]
 #[display(fmt = "{} ID={} Client={} Amount={:?}", typ, tx, client, amount)]
 pub struct Tx {
   #[serde(rename = "type")]
@@ -35,26 +50,659 @@ pub struct Tx {
   pub client: u16,
   pub tx: u32,
   pub amount: Option<Decimal>,
+
+  /// The client receiving the funds of a transfer, whose source is *client*.
+  #[serde(default)]
+  pub destination: Option<u16>,
+
+  /// Who issued an administrative transaction (e.g. an unlock).
+  #[serde(default)]
+  pub operator: Option<String>,
+
+  /// Why an administrative transaction (e.g. an unlock) was issued.
+  #[serde(default)]
+  pub reason: Option<String>,
+
+  /// When the transaction was issued, in seconds since the Unix epoch. Withdrawals and
+  /// transfers need it for the [velocity limits](crate::VelocityLimits), and any
+  /// transaction with a timestamp advances the clock by which authorizations expire.
+  #[serde(default)]
+  pub timestamp: Option<u64>,
 }
 
 impl Tx {
+  pub fn new_transfer(tx: u32, source: u16, destination: u16, amount: Decimal) -> Self {
+    Self {
+      typ: TxType::Transfer,
+      client: source,
+      tx,
+      amount: Some(amount),
+      destination: Some(destination),
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
+  }
+
   pub fn new_deposit(tx: u32, client: u16, amount: Decimal) -> Self {
-    Self { typ: TxType::Deposit, client, tx, amount: Some(amount) }
+    Self {
+      typ: TxType::Deposit,
+      client,
+      tx,
+      amount: Some(amount),
+      destination: None,
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
   }
 
   pub fn new_withdraw(tx: u32, client: u16, amount: Decimal) -> Self {
-    Self { typ: TxType::Withdrawal, client, tx, amount: Some(amount) }
+    Self {
+      typ: TxType::Withdrawal,
+      client,
+      tx,
+      amount: Some(amount),
+      destination: None,
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
   }
 
   pub fn new_dispute(tx: u32, client: u16) -> Self {
-    Self { typ: TxType::Dispute, client, tx, amount: None }
+    Self {
+      typ: TxType::Dispute,
+      client,
+      tx,
+      amount: None,
+      destination: None,
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
+  }
+
+  pub fn new_partial_dispute(tx: u32, client: u16, amount: Decimal) -> Self {
+    Self {
+      typ: TxType::Dispute,
+      client,
+      tx,
+      amount: Some(amount),
+      destination: None,
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
   }
 
   pub fn new_resolve(tx: u32, client: u16) -> Self {
-    Self { typ: TxType::Resolve, client, tx, amount: None }
+    Self {
+      typ: TxType::Resolve,
+      client,
+      tx,
+      amount: None,
+      destination: None,
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
   }
 
   pub fn new_chargeback(tx: u32, client: u16) -> Self {
-    Self { typ: TxType::Chargeback, client, tx, amount: None }
+    Self {
+      typ: TxType::Chargeback,
+      client,
+      tx,
+      amount: None,
+      destination: None,
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
+  }
+
+  pub fn new_limit(
+    tx: u32,
+    client: u16,
+    limit: Decimal,
+    operator: &str,
+    reason: &str,
+  ) -> Self {
+    Self {
+      typ: TxType::Limit,
+      client,
+      tx,
+      amount: Some(limit),
+      destination: None,
+      operator: Some(operator.to_owned()),
+      reason: Some(reason.to_owned()),
+      timestamp: None,
+    }
+  }
+
+  pub fn new_authorize(tx: u32, client: u16, amount: Decimal) -> Self {
+    Self {
+      typ: TxType::Authorize,
+      client,
+      tx,
+      amount: Some(amount),
+      destination: None,
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
+  }
+
+  pub fn new_capture(tx: u32, client: u16) -> Self {
+    Self {
+      typ: TxType::Capture,
+      client,
+      tx,
+      amount: None,
+      destination: None,
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
+  }
+
+  pub fn new_partial_capture(tx: u32, client: u16, amount: Decimal) -> Self {
+    Self {
+      typ: TxType::Capture,
+      client,
+      tx,
+      amount: Some(amount),
+      destination: None,
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
+  }
+
+  pub fn new_void(tx: u32, client: u16) -> Self {
+    Self {
+      typ: TxType::Void,
+      client,
+      tx,
+      amount: None,
+      destination: None,
+      operator: None,
+      reason: None,
+      timestamp: None,
+    }
+  }
+
+  pub fn new_unlock(tx: u32, client: u16, operator: &str, reason: &str) -> Self {
+    Self {
+      typ: TxType::Unlock,
+      client,
+      tx,
+      amount: None,
+      destination: None,
+      operator: Some(operator.to_owned()),
+      reason: Some(reason.to_owned()),
+      timestamp: None,
+    }
+  }
+
+  pub fn new_release(tx: u32, client: u16, operator: &str, reason: &str) -> Self {
+    Self {
+      typ: TxType::Release,
+      client,
+      tx,
+      amount: None,
+      destination: None,
+      operator: Some(operator.to_owned()),
+      reason: Some(reason.to_owned()),
+      timestamp: None,
+    }
+  }
+
+  pub fn new_partial_release(
+    tx: u32,
+    client: u16,
+    amount: Decimal,
+    operator: &str,
+    reason: &str,
+  ) -> Self {
+    Self { amount: Some(amount), ..Self::new_release(tx, client, operator, reason) }
+  }
+
+  pub fn with_timestamp(mut self, timestamp: u64) -> Self {
+    self.timestamp = Some(timestamp);
+    self
+  }
+}
+
+/// A transaction, holding only the fields that make sense for its type.
+///
+/// A transaction is (de)serialized in the same flat form as a [raw record](Tx), so it can
+/// be read straight from a CSV record or a JSON object.
+///
+/// # Errors
+///
+/// * A deposit, withdrawal, transfer, limit or authorization without an amount is
+///   rejected with [MissingAmount](TxErrKind::MissingAmount).
+///
+/// * A resolve, chargeback, unlock or void with an amount is rejected with
+///   [ExtraneousAmount](TxErrKind::ExtraneousAmount).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Transaction {
+  Deposit {
+    tx: TxId,
+    client: ClientId,
+    amount: Decimal,
+    timestamp: Option<u64>,
+  },
+  Withdrawal {
+    tx: TxId,
+    client: ClientId,
+    amount: Decimal,
+    timestamp: Option<u64>,
+  },
+  /// A dispute of the whole referenced transaction, or only of *amount* of it.
+  Dispute {
+    tx: TxId,
+    client: ClientId,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+  },
+  Resolve {
+    tx: TxId,
+    client: ClientId,
+    timestamp: Option<u64>,
+  },
+  Chargeback {
+    tx: TxId,
+    client: ClientId,
+    timestamp: Option<u64>,
+  },
+  Unlock {
+    tx: TxId,
+    client: ClientId,
+    operator: String,
+    reason: String,
+    timestamp: Option<u64>,
+  },
+  /// A transfer of *amount* from *client* to *destination*.
+  Transfer {
+    tx: TxId,
+    client: ClientId,
+    destination: ClientId,
+    amount: Decimal,
+    timestamp: Option<u64>,
+  },
+  /// An administrative change of *client*'s credit limit to *amount*.
+  Limit {
+    tx: TxId,
+    client: ClientId,
+    amount: Decimal,
+    operator: String,
+    reason: String,
+    timestamp: Option<u64>,
+  },
+  Authorize {
+    tx: TxId,
+    client: ClientId,
+    amount: Decimal,
+    timestamp: Option<u64>,
+  },
+  /// A capture of the whole referenced authorization, or only of *amount* of it.
+  Capture {
+    tx: TxId,
+    client: ClientId,
+    amount: Option<Decimal>,
+    timestamp: Option<u64>,
+  },
+  Void {
+    tx: TxId,
+    client: ClientId,
+    timestamp: Option<u64>,
+  },
+  /// An administrative release of *client*'s carried-over held funds, or only of *amount*
+  /// of them.
+  Release {
+    tx: TxId,
+    client: ClientId,
+    amount: Option<Decimal>,
+    operator: String,
+    reason: String,
+    timestamp: Option<u64>,
+  },
+}
+
+impl Transaction {
+  pub fn typ(&self) -> TxType {
+    match self {
+      Transaction::Deposit { .. } => TxType::Deposit,
+      Transaction::Withdrawal { .. } => TxType::Withdrawal,
+      Transaction::Dispute { .. } => TxType::Dispute,
+      Transaction::Resolve { .. } => TxType::Resolve,
+      Transaction::Chargeback { .. } => TxType::Chargeback,
+      Transaction::Unlock { .. } => TxType::Unlock,
+      Transaction::Transfer { .. } => TxType::Transfer,
+      Transaction::Limit { .. } => TxType::Limit,
+      Transaction::Authorize { .. } => TxType::Authorize,
+      Transaction::Capture { .. } => TxType::Capture,
+      Transaction::Void { .. } => TxType::Void,
+      Transaction::Release { .. } => TxType::Release,
+    }
+  }
+
+  pub fn tx(&self) -> TxId {
+    match self {
+      Transaction::Deposit { tx, .. }
+      | Transaction::Withdrawal { tx, .. }
+      | Transaction::Dispute { tx, .. }
+      | Transaction::Resolve { tx, .. }
+      | Transaction::Chargeback { tx, .. }
+      | Transaction::Unlock { tx, .. }
+      | Transaction::Transfer { tx, .. }
+      | Transaction::Limit { tx, .. }
+      | Transaction::Authorize { tx, .. }
+      | Transaction::Capture { tx, .. }
+      | Transaction::Void { tx, .. }
+      | Transaction::Release { tx, .. } => *tx,
+    }
+  }
+
+  pub fn client(&self) -> ClientId {
+    match self {
+      Transaction::Deposit { client, .. }
+      | Transaction::Withdrawal { client, .. }
+      | Transaction::Dispute { client, .. }
+      | Transaction::Resolve { client, .. }
+      | Transaction::Chargeback { client, .. }
+      | Transaction::Unlock { client, .. }
+      | Transaction::Transfer { client, .. }
+      | Transaction::Limit { client, .. }
+      | Transaction::Authorize { client, .. }
+      | Transaction::Capture { client, .. }
+      | Transaction::Void { client, .. }
+      | Transaction::Release { client, .. } => *client,
+    }
+  }
+
+  pub fn amount(&self) -> Option<Decimal> {
+    match self {
+      Transaction::Deposit { amount, .. }
+      | Transaction::Withdrawal { amount, .. }
+      | Transaction::Transfer { amount, .. }
+      | Transaction::Limit { amount, .. }
+      | Transaction::Authorize { amount, .. } => Some(*amount),
+      Transaction::Dispute { amount, .. }
+      | Transaction::Capture { amount, .. }
+      | Transaction::Release { amount, .. } => *amount,
+      _ => None,
+    }
+  }
+
+  pub fn timestamp(&self) -> Option<u64> {
+    match self {
+      Transaction::Deposit { timestamp, .. }
+      | Transaction::Withdrawal { timestamp, .. }
+      | Transaction::Dispute { timestamp, .. }
+      | Transaction::Resolve { timestamp, .. }
+      | Transaction::Chargeback { timestamp, .. }
+      | Transaction::Unlock { timestamp, .. }
+      | Transaction::Transfer { timestamp, .. }
+      | Transaction::Limit { timestamp, .. }
+      | Transaction::Authorize { timestamp, .. }
+      | Transaction::Capture { timestamp, .. }
+      | Transaction::Void { timestamp, .. }
+      | Transaction::Release { timestamp, .. } => *timestamp,
+    }
+  }
+}
+
+impl TryFrom<&Tx> for Transaction {
+  type Error = TxErrKind;
+
+  fn try_from(raw: &Tx) -> Result<Self, TxErrKind> {
+    let tx = TxId::new(raw.tx);
+    let client = ClientId::new(raw.client);
+
+    let timestamp = raw.timestamp;
+
+    let ensure_no_amount = || match raw.amount {
+      Some(_) => Err(TxErrKind::ExtraneousAmount),
+      None => Ok(()),
+    };
+
+    Ok(match raw.typ {
+      TxType::Deposit => Transaction::Deposit {
+        tx,
+        client,
+        amount: raw.amount.ok_or(TxErrKind::MissingAmount)?,
+        timestamp,
+      },
+      TxType::Withdrawal => Transaction::Withdrawal {
+        tx,
+        client,
+        amount: raw.amount.ok_or(TxErrKind::MissingAmount)?,
+        timestamp,
+      },
+      TxType::Dispute => {
+        Transaction::Dispute { tx, client, amount: raw.amount, timestamp }
+      }
+      TxType::Resolve => {
+        ensure_no_amount()?;
+        Transaction::Resolve { tx, client, timestamp }
+      }
+      TxType::Chargeback => {
+        ensure_no_amount()?;
+        Transaction::Chargeback { tx, client, timestamp }
+      }
+      TxType::Unlock => {
+        ensure_no_amount()?;
+        Transaction::Unlock {
+          tx,
+          client,
+          operator: raw.operator.clone().unwrap_or_default(),
+          reason: raw.reason.clone().unwrap_or_default(),
+          timestamp,
+        }
+      }
+      TxType::Transfer => Transaction::Transfer {
+        tx,
+        client,
+        destination: ClientId::new(raw.destination.ok_or(TxErrKind::MissingDestination)?),
+        amount: raw.amount.ok_or(TxErrKind::MissingAmount)?,
+        timestamp,
+      },
+      TxType::Limit => Transaction::Limit {
+        tx,
+        client,
+        amount: raw.amount.ok_or(TxErrKind::MissingAmount)?,
+        operator: raw.operator.clone().unwrap_or_default(),
+        reason: raw.reason.clone().unwrap_or_default(),
+        timestamp,
+      },
+      TxType::Authorize => Transaction::Authorize {
+        tx,
+        client,
+        amount: raw.amount.ok_or(TxErrKind::MissingAmount)?,
+        timestamp,
+      },
+      TxType::Capture => {
+        Transaction::Capture { tx, client, amount: raw.amount, timestamp }
+      }
+      TxType::Void => {
+        ensure_no_amount()?;
+        Transaction::Void { tx, client, timestamp }
+      }
+      TxType::Release => Transaction::Release {
+        tx,
+        client,
+        amount: raw.amount,
+        operator: raw.operator.clone().unwrap_or_default(),
+        reason: raw.reason.clone().unwrap_or_default(),
+        timestamp,
+      },
+    })
+  }
+}
+
+impl From<&Transaction> for Tx {
+  fn from(transaction: &Transaction) -> Self {
+    let (operator, reason) = match transaction {
+      Transaction::Unlock { operator, reason, .. }
+      | Transaction::Limit { operator, reason, .. }
+      | Transaction::Release { operator, reason, .. } => {
+        (Some(operator.clone()), Some(reason.clone()))
+      }
+      _ => (None, None),
+    };
+
+    let destination = match transaction {
+      Transaction::Transfer { destination, .. } => Some(destination.value()),
+      _ => None,
+    };
+
+    Self {
+      typ: transaction.typ(),
+      client: transaction.client().value(),
+      tx: transaction.tx().value(),
+      amount: transaction.amount(),
+      destination,
+      operator,
+      reason,
+      timestamp: transaction.timestamp(),
+    }
+  }
+}
+
+impl fmt::Display for Transaction {
+  fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+    Tx::from(self).fmt(f)
+  }
+}
+
+impl Serialize for Transaction {
+  fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+    Tx::from(self).serialize(serializer)
+  }
+}
+
+impl<'de> Deserialize<'de> for Transaction {
+  fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+    let raw = Tx::deserialize(deserializer)?;
+
+    Transaction::try_from(&raw).map_err(|kind| {
+      D::Error::custom(format_args!(
+        "{} ID={} Client={}: {}",
+        raw.typ, raw.tx, raw.client, kind
+      ))
+    })
+  }
+}
+
+#[cfg(test)]
+mod tx_tests {
+  use crate::{ClientId, Transaction, Tx, TxId};
+  use rust_decimal::Decimal;
+  use std::convert::TryFrom;
+
+  fn read(input: &str) -> Vec<Result<Transaction, String>> {
+    let mut reader = csv::ReaderBuilder::new()
+      .flexible(true)
+      .trim(csv::Trim::All)
+      .from_reader(input.as_bytes());
+
+    reader.deserialize().map(|tx| tx.map_err(|e: csv::Error| e.to_string())).collect()
+  }
+
+  #[test]
+  fn deserialize() {
+    let txs = read(
+      "type,client,tx,amount\n\
+       deposit,1,1,1.5\n\
+       withdrawal,1,2,0.5\n\
+       dispute,1,1,\n\
+       dispute,1,1,0.5\n\
+       resolve,1,1,\n\
+       chargeback,1,1\n",
+    );
+
+    let tx = TxId::new;
+    let client = ClientId::new(1);
+    let amount = |value: &str| value.parse::<Decimal>().unwrap();
+
+    assert_eq!(
+      txs,
+      vec![
+        Ok(Transaction::Deposit {
+          tx: tx(1),
+          client,
+          amount: amount("1.5"),
+          timestamp: None,
+        }),
+        Ok(Transaction::Withdrawal {
+          tx: tx(2),
+          client,
+          amount: amount("0.5"),
+          timestamp: None,
+        }),
+        Ok(Transaction::Dispute { tx: tx(1), client, amount: None, timestamp: None }),
+        Ok(Transaction::Dispute {
+          tx: tx(1),
+          client,
+          amount: Some(amount("0.5")),
+          timestamp: None,
+        }),
+        Ok(Transaction::Resolve { tx: tx(1), client, timestamp: None }),
+        Ok(Transaction::Chargeback { tx: tx(1), client, timestamp: None }),
+      ]
+    );
+  }
+
+  #[test]
+  fn invalid_amounts() {
+    let txs = read(
+      "type,client,tx,amount\n\
+       deposit,1,1,\n\
+       withdrawal,1,2\n\
+       resolve,1,1,1.0\n\
+       chargeback,1,1,1.0\n",
+    );
+
+    let errs: Vec<_> = txs.into_iter().map(Result::unwrap_err).collect();
+    let missing = "Transaction must provide an amount";
+    assert!(errs[0].contains(&format!("Deposit ID=1 Client=1: {}", missing)));
+    assert!(errs[1].contains(&format!("Withdrawal ID=2 Client=1: {}", missing)));
+    assert!(
+      errs[2].contains("Resolve ID=1 Client=1: Transaction has an unexpected amount")
+    );
+    assert!(
+      errs[3].contains("Chargeback ID=1 Client=1: Transaction has an unexpected amount")
+    );
+  }
+
+  #[test]
+  fn raw_roundtrip() {
+    let raw = Tx::new_unlock(3, 1, "risk", "review");
+    let transaction = Transaction::try_from(&raw).unwrap();
+
+    assert_eq!(
+      transaction,
+      Transaction::Unlock {
+        tx: TxId::new(3),
+        client: ClientId::new(1),
+        operator: String::from("risk"),
+        reason: String::from("review"),
+        timestamp: None,
+      }
+    );
+
+    let json = serde_json::to_string(&transaction).unwrap();
+    assert_eq!(json, serde_json::to_string(&raw).unwrap());
+    assert_eq!(serde_json::from_str::<Transaction>(&json).unwrap(), transaction);
+
+    // Every type of transaction keeps its timestamp.
+    let raw = Tx::new_deposit(4, 1, Decimal::ONE).with_timestamp(60);
+    let transaction = Transaction::try_from(&raw).unwrap();
+    assert_eq!(transaction.timestamp(), Some(60));
+    assert_eq!(Tx::from(&transaction).timestamp, Some(60));
   }
 }
@@ -0,0 +1,76 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{ClientId, TxErrKind, TxId};
+use derive_more::Display;
+use serde::{Deserialize, Serialize};
+
+/// An unlock is an administrative reactivation of a locked account.
+///
+/// An unlock must turn a locked account back into an unlocked account, keeping all of its
+/// funds and history.
+///
+/// # Errors
+///
+/// * An error is thrown if the [client ID](ClientId) and account do not already exist.
+///
+/// * An error is thrown if the [transaction ID](TxId) has already been used.
+///
+/// * An error is thrown if the operator or the reason is missing.
+///
+/// * An error is thrown if the account is not locked.
+#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
+#[display(fmt = "Unlock {} {} Operator={} Reason={}", id, client, operator, reason)]
+pub struct Unlock {
+  id: TxId,
+  client: ClientId,
+  operator: String,
+  reason: String,
+}
+
+impl Unlock {
+  pub fn new(
+    id: TxId,
+    client: ClientId,
+    operator: String,
+    reason: String,
+  ) -> Result<Self, TxErrKind> {
+    if operator.is_empty() || reason.is_empty() {
+      Err(TxErrKind::MissingAdminDetails)
+    } else {
+      Ok(Self { id, client, operator, reason })
+    }
+  }
+
+  /// Get the unlock's id.
+  pub fn id(&self) -> TxId {
+    self.id
+  }
+
+  /// Get the unlock's client.
+  pub fn client(&self) -> ClientId {
+    self.client
+  }
+
+  /// Get who unlocked the account.
+  pub fn operator(&self) -> &str {
+    &self.operator
+  }
+
+  /// Get why the account was unlocked.
+  pub fn reason(&self) -> &str {
+    &self.reason
+  }
+}
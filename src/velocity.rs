@@ -0,0 +1,222 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{ClientId, TxErr, TxErrKind, TxResult};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// The window of [VelocityRule::daily_amount], in seconds.
+pub const DAY: u64 = 24 * 60 * 60;
+
+/// The window of [VelocityRule::hourly_count], in seconds.
+pub const HOUR: u64 = 60 * 60;
+
+/// Velocity limits on the withdrawals of a client, or of all clients without a rule of
+/// their own.
+///
+/// The windows are rolling and based on the timestamps of the withdrawals (see
+/// [Tx::timestamp](crate::Tx)), so withdrawals subject to a rule must have one.
+///
+/// # Errors
+///
+/// * An error is thrown if the amount is negative.
+///
+/// # Notes
+///
+/// * A client's own rule replaces the default rule entirely, rather than only the limits
+///   it sets.
+#[derive(Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub struct VelocityRule {
+  /// The client the rule applies to, or none for the default rule.
+  #[serde(default)]
+  pub client: Option<u16>,
+
+  /// The most that may be withdrawn in any 24 hours.
+  #[serde(default)]
+  pub daily_amount: Option<Decimal>,
+
+  /// The most withdrawals that may be made in any hour.
+  #[serde(default)]
+  pub hourly_count: Option<u32>,
+}
+
+impl VelocityRule {
+  /// Check a withdrawal of *amount* at *timestamp* against the client's *history* of
+  /// earlier withdrawals.
+  ///
+  /// Withdrawals are counted in a window if they are less than its length apart from
+  /// *timestamp* in either direction, so that input that is slightly out of order cannot
+  /// be used to get around the limits. A withdrawal a day or more behind the latest one
+  /// is rejected, as the history its windows reach back to is no longer kept.
+  fn check(
+    &self,
+    history: &[Withdrawal],
+    timestamp: u64,
+    amount: Decimal,
+  ) -> Result<(), TxErrKind> {
+    let latest = history.iter().map(|withdrawal| withdrawal.timestamp).max();
+    if latest.is_some_and(|latest| timestamp.saturating_add(DAY) <= latest) {
+      return Err(TxErrKind::StaleTimestamp);
+    }
+
+    let within = |window| {
+      move |withdrawal: &&Withdrawal| withdrawal.timestamp.abs_diff(timestamp) < window
+    };
+
+    if let Some(max) = self.daily_amount {
+      let total = history
+        .iter()
+        .filter(within(DAY))
+        .try_fold(amount, |total, withdrawal| total.checked_add(withdrawal.amount));
+
+      if total.is_none_or(|total| total > max) {
+        return Err(TxErrKind::VelocityExceeded);
+      }
+    }
+
+    if let Some(max) = self.hourly_count {
+      if history.iter().filter(within(HOUR)).count() >= max as usize {
+        return Err(TxErrKind::VelocityExceeded);
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// A withdrawal counted towards the velocity limits of its client.
+#[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone, Copy)]
+pub(crate) struct Withdrawal {
+  pub(crate) client: ClientId,
+  pub(crate) timestamp: u64,
+  pub(crate) amount: Decimal,
+}
+
+/// The velocity limits on withdrawals, per client and by default.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VelocityLimits {
+  default: Option<VelocityRule>,
+  clients: HashMap<ClientId, VelocityRule>,
+}
+
+impl VelocityLimits {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Add a rule, replacing any previous rule for the same client (or the previous default
+  /// rule).
+  pub fn add_rule(&mut self, rule: &VelocityRule) -> TxResult {
+    if rule.daily_amount.is_some_and(|amount| amount.is_sign_negative()) {
+      let err = TxErr::new(TxErrKind::NegativeAmount);
+
+      return Err(match rule.client {
+        Some(client) => err.with_client(ClientId::new(client)),
+        None => err,
+      });
+    }
+
+    match rule.client {
+      Some(client) => {
+        self.clients.insert(ClientId::new(client), *rule);
+      }
+      None => self.default = Some(*rule),
+    }
+
+    Ok(())
+  }
+
+  /// Get the rule that applies to *client*, if any.
+  pub fn rule(&self, client: ClientId) -> Option<&VelocityRule> {
+    self.clients.get(&client).or(self.default.as_ref())
+  }
+
+  /// Check a withdrawal of *amount* at *timestamp* by *client*, given their *history* of
+  /// earlier withdrawals.
+  pub(crate) fn check(
+    &self,
+    client: ClientId,
+    history: &[Withdrawal],
+    timestamp: Option<u64>,
+    amount: Decimal,
+  ) -> Result<(), TxErrKind> {
+    match self.rule(client) {
+      Some(rule) => {
+        let timestamp = timestamp.ok_or(TxErrKind::MissingTimestamp)?;
+        rule.check(history, timestamp, amount)
+      }
+      None => Ok(()),
+    }
+  }
+}
+
+#[cfg(test)]
+mod velocity_tests {
+  use crate::velocity::{Withdrawal, HOUR};
+  use crate::{ClientId, TxErrKind, VelocityLimits, VelocityRule};
+  use rust_decimal::Decimal;
+
+  #[test]
+  fn check() {
+    let mut limits = VelocityLimits::new();
+    let default = VelocityRule {
+      client: None,
+      daily_amount: Some(Decimal::from(100)),
+      hourly_count: None,
+    };
+    assert!(limits.add_rule(&default).is_ok());
+    let own = VelocityRule { client: Some(2), daily_amount: None, hourly_count: Some(2) };
+    assert!(limits.add_rule(&own).is_ok());
+    let negative = VelocityRule { daily_amount: Some(Decimal::from(-1)), ..own };
+    assert_eq!(
+      limits.add_rule(&negative).map_err(|err| err.kind()),
+      Err(TxErrKind::NegativeAmount)
+    );
+
+    let (one, two) = (ClientId::new(1), ClientId::new(2));
+    let withdrawal = |client, timestamp, amount: i64| Withdrawal {
+      client,
+      timestamp,
+      amount: Decimal::from(amount),
+    };
+
+    let history = [withdrawal(one, 0, 60), withdrawal(one, 10 * HOUR, 30)];
+    let check = |client, timestamp, amount: i64| {
+      limits.check(client, &history, timestamp, Decimal::from(amount))
+    };
+
+    assert_eq!(check(one, Some(20 * HOUR), 10), Ok(()));
+    assert_eq!(check(one, Some(20 * HOUR), 11), Err(TxErrKind::VelocityExceeded));
+    // The first withdrawal is out of the window.
+    assert_eq!(check(one, Some(24 * HOUR), 70), Ok(()));
+    assert_eq!(check(one, None, 1), Err(TxErrKind::MissingTimestamp));
+
+    // The client's own rule replaces the default one.
+    let history = [withdrawal(two, 0, 1000), withdrawal(two, HOUR / 2, 1000)];
+    let check = |timestamp| limits.check(two, &history, Some(timestamp), Decimal::ONE);
+    assert_eq!(check(HOUR - 1), Err(TxErrKind::VelocityExceeded));
+    assert_eq!(check(HOUR), Ok(()));
+
+    // Other clients get the default rule.
+    let three = ClientId::new(3);
+    assert_eq!(limits.check(three, &[], Some(0), Decimal::from(100)), Ok(()));
+    assert_eq!(
+      limits.check(three, &[], None, Decimal::ONE),
+      Err(TxErrKind::MissingTimestamp)
+    );
+    assert!(VelocityLimits::new().check(one, &history, None, Decimal::MAX).is_ok());
+  }
+}
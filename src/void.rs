@@ -0,0 +1,49 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{ClientId, TxId};
+use derive_more::Display;
+use derive_new::new;
+
+/// A void cancels an [authorization](crate::Authorization).
+///
+/// A void must move the authorized funds back to the available funds.
+///
+/// # Errors
+///
+/// * An error is thrown if the [client ID](ClientId) and account do not already exist.
+///
+/// * An error is thrown if the [transaction ID](TxId) does not already exist.
+///
+/// * An error is thrown if the [transaction ID](TxId) does not refer to an open
+///   authorization associated with [the corresponding client](ClientId).
+#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Copy, new)]
+#[display(fmt = "Void {} {}", id, client)]
+pub struct Void {
+  id: TxId,
+  client: ClientId,
+}
+
+impl Void {
+  /// Get the void's id.
+  pub fn id(&self) -> TxId {
+    self.id
+  }
+
+  /// Get the void's client.
+  pub fn client(&self) -> ClientId {
+    self.client
+  }
+}
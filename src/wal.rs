@@ -0,0 +1,434 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use crate::{Db, Tx, TxErr};
+use derive_more::{Display, From};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::Path;
+
+#[derive(Display, Debug, From)]
+pub enum WalErr {
+  #[display(fmt = "IO Error: {}", _0)]
+  Io(io::Error),
+
+  #[display(fmt = "Corrupt write-ahead log record at line {}", _0)]
+  #[from(ignore)]
+  Corrupt(u64),
+
+  #[display(fmt = "Write-ahead log record {} could not be replayed: {}", _0, _1)]
+  Replay(u64, TxErr),
+
+  #[display(
+    fmt = "Write-ahead log record {} found where record {} was expected",
+    _0,
+    _1
+  )]
+  #[from(ignore)]
+  Sequence(u64, u64),
+
+  #[display(
+    fmt = "Write-ahead log was written with {}={}, but it is now {}; rerun with the \
+           original configuration",
+    _0,
+    _1,
+    _2
+  )]
+  #[from(ignore)]
+  Config(String, String, String),
+}
+
+impl std::error::Error for WalErr {}
+
+/// A record in the [write-ahead log](Wal).
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WalRecord {
+  /// The sequence number of the transaction, i.e. the length of the database's hash chain
+  /// after the transaction was applied.
+  pub seq: u64,
+
+  /// The input the transaction was read from, empty in records written before inputs
+  /// were recorded.
+  #[serde(default)]
+  pub input: String,
+
+  /// The input line the transaction was read from.
+  pub line: u64,
+
+  pub tx: Tx,
+}
+
+/// The configuration that the transactions in a [write-ahead log](Wal) were applied
+/// under, as setting names and values. Replaying them under a different configuration
+/// could fail, or lead to a different state.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+pub struct WalConfig(BTreeMap<String, String>);
+
+impl WalConfig {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Set the setting *name* to *value*.
+  pub fn with(mut self, name: &str, value: impl Display) -> Self {
+    self.0.insert(String::from(name), value.to_string());
+    self
+  }
+
+  /// Check that *self*, as found in a log, matches the *current* configuration.
+  fn check(&self, current: &WalConfig) -> Result<(), WalErr> {
+    let unset = String::from("(unset)");
+
+    for name in self.0.keys().chain(current.0.keys()) {
+      let (logged, now) = (self.0.get(name), current.0.get(name));
+
+      if logged != now {
+        let (logged, now) = (logged.unwrap_or(&unset), now.unwrap_or(&unset));
+        return Err(WalErr::Config(name.clone(), logged.clone(), now.clone()));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+/// A line in the [write-ahead log](Wal): the configuration on the first line, followed
+/// by the records.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum Entry {
+  Config { config: WalConfig },
+  Record(WalRecord),
+}
+
+/// An append-only write-ahead log of applied transactions.
+///
+/// Every line is written as the CRC-32 checksum of its entry (in hexadecimal) followed
+/// by the entry itself as JSON, and is synced to disk before [append](Wal::append)
+/// returns. The first line holds the [configuration](WalConfig) the records were applied
+/// under, and the log can only be opened under the same configuration.
+///
+/// # Notes
+///
+/// * A final record with an invalid checksum or without a trailing newline is the result
+///   of a torn write and is truncated when the log is opened. An invalid record anywhere
+///   else is an error.
+/// * A log without records takes on the configuration it is opened under. A log written
+///   before configurations were recorded has no configuration line, and is opened under
+///   any configuration.
+#[derive(Debug)]
+pub struct Wal {
+  file: File,
+  config: WalConfig,
+}
+
+impl Wal {
+  /// Open (or create) the log at *path* under *config*, returning it along with all of
+  /// its valid records.
+  pub fn open(path: &Path, config: &WalConfig) -> Result<(Self, Vec<WalRecord>), WalErr> {
+    let mut file = OpenOptions::new().read(true).append(true).create(true).open(path)?;
+
+    let mut logged = None;
+    let mut records = Vec::new();
+    let mut valid_len = 0;
+    let mut torn = false;
+
+    {
+      let mut reader = BufReader::new(&mut file);
+      let mut buf = Vec::new();
+      let mut line = 0;
+
+      loop {
+        buf.clear();
+        if reader.read_until(b'\n', &mut buf)? == 0 {
+          break;
+        }
+
+        line += 1;
+
+        if torn {
+          // There is more data after an invalid record, so it was not a torn write.
+          return Err(WalErr::Corrupt(line - 1));
+        }
+
+        match Self::decode(&buf) {
+          Some(Entry::Config { config }) if line == 1 => {
+            valid_len += buf.len() as u64;
+            logged = Some(config);
+          }
+          Some(Entry::Config { .. }) => return Err(WalErr::Corrupt(line)),
+          Some(Entry::Record(record)) => {
+            valid_len += buf.len() as u64;
+            records.push(record);
+          }
+          None => torn = true,
+        }
+      }
+    }
+
+    if torn {
+      file.set_len(valid_len)?;
+      file.sync_all()?;
+    }
+
+    let mut wal = Self { file, config: config.clone() };
+
+    // A log without records has nothing to replay, so it takes on the configuration.
+    if records.is_empty() {
+      wal.truncate()?;
+    } else if let Some(logged) = logged {
+      logged.check(config)?;
+    }
+
+    Ok((wal, records))
+  }
+
+  fn decode(buf: &[u8]) -> Option<Entry> {
+    let buf = buf.strip_suffix(b"\n")?;
+    let (checksum, json) = (buf.get(..8)?, buf.get(9..)?);
+    let checksum = u32::from_str_radix(std::str::from_utf8(checksum).ok()?, 16).ok()?;
+
+    if checksum != crc32fast::hash(json) {
+      return None;
+    }
+
+    serde_json::from_slice(json).ok()
+  }
+
+  /// Durably append *record* to the log.
+  pub fn append(&mut self, record: &WalRecord) -> Result<(), WalErr> {
+    self.write(record)
+  }
+
+  /// Durably write the configuration as the first line of an empty log.
+  fn write_config(&mut self) -> Result<(), WalErr> {
+    let config = self.config.clone();
+    self.write(&Entry::Config { config })
+  }
+
+  fn write<T: Serialize>(&mut self, entry: &T) -> Result<(), WalErr> {
+    let json = serde_json::to_vec(entry).map_err(io::Error::from)?;
+
+    let mut buf = Vec::with_capacity(json.len() + 10);
+    write!(buf, "{:08x} ", crc32fast::hash(&json))?;
+    buf.extend_from_slice(&json);
+    buf.push(b'\n');
+
+    self.file.write_all(&buf)?;
+    self.file.sync_data()?;
+
+    Ok(())
+  }
+
+  /// Replay *records* into *db*, skipping records that are already part of its state
+  /// (e.g. because it was restored from a snapshot taken after they were written).
+  ///
+  /// The sequence numbers must be contiguous, and the first record that is not part of
+  /// the state must directly follow it. A gap or a duplicate is an error, since replaying
+  /// around it would silently lead to a different state.
+  ///
+  /// Returns the number of replayed records.
+  pub fn replay(records: &[WalRecord], db: &mut Db) -> Result<usize, WalErr> {
+    let mut replayed = 0;
+    let applied = db.chain().len();
+    let first = records.first().map_or(applied + 1, |record| record.seq.min(applied + 1));
+
+    for (next, record) in (first..).zip(records) {
+      if record.seq != next {
+        return Err(WalErr::Sequence(record.seq, next));
+      }
+
+      if record.seq > applied {
+        db.process(&record.tx).map_err(|err| WalErr::Replay(record.seq, err))?;
+        replayed += 1;
+      }
+    }
+
+    Ok(replayed)
+  }
+
+  /// Durably remove all records from the log, once they are covered by a saved snapshot.
+  /// The configuration is kept.
+  pub fn truncate(&mut self) -> Result<(), WalErr> {
+    self.file.set_len(0)?;
+    self.file.sync_all()?;
+    self.write_config()
+  }
+
+  /// The transactions logged by *records*, by input and line, so that a resumed run can
+  /// skip the rows that were already applied. A row should only be skipped if it is the
+  /// same transaction, since the same name may since refer to a new input (e.g. a
+  /// rewritten file or the standard input).
+  ///
+  /// Only the records after the first *applied* transactions count: those were part of
+  /// the state the log was replayed into (e.g. a snapshot), so their inputs were finished.
+  pub fn covered(records: &[WalRecord], applied: u64) -> HashMap<(&str, u64), &Tx> {
+    records
+      .iter()
+      .filter(|record| record.seq > applied && !record.input.is_empty())
+      .map(|record| ((record.input.as_str(), record.line), &record.tx))
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod wal_tests {
+  use crate::{Db, Tx, Wal, WalConfig, WalErr, WalRecord};
+  use rust_decimal::Decimal;
+  use std::fs::{self, OpenOptions};
+  use std::io::Write;
+  use std::path::{Path, PathBuf};
+
+  fn path(name: &str) -> PathBuf {
+    let path =
+      std::env::temp_dir().join(format!("tx-engine-{}-{}", std::process::id(), name));
+    let _ = fs::remove_file(&path);
+    path
+  }
+
+  fn fill(path: &Path) -> Db {
+    let mut db = Db::new();
+    let (mut wal, records) = Wal::open(path, &WalConfig::new()).unwrap();
+    assert!(records.is_empty());
+
+    for (line, tx) in
+      [Tx::new_deposit(1, 1, Decimal::from(5)), Tx::new_withdraw(2, 1, Decimal::from(2))]
+        .iter()
+        .enumerate()
+    {
+      db.process(tx).unwrap();
+      let record = WalRecord {
+        seq: db.chain().len(),
+        input: String::from("input.csv"),
+        line: line as u64 + 2,
+        tx: tx.clone(),
+      };
+      wal.append(&record).unwrap();
+    }
+
+    db
+  }
+
+  #[test]
+  fn replay() {
+    let path = path("replay");
+    let db = fill(&path);
+
+    let (_, records) = Wal::open(&path, &WalConfig::new()).unwrap();
+    assert_eq!(records.len(), 2);
+
+    let mut replayed = Db::new();
+    assert_eq!(Wal::replay(&records, &mut replayed).unwrap(), 2);
+    assert_eq!(replayed.chain(), db.chain());
+    assert_eq!(Wal::replay(&records, &mut replayed).unwrap(), 0);
+
+    let covered = Wal::covered(&records, 0);
+    assert_eq!(covered.get(&("input.csv", 2)), Some(&&records[0].tx));
+    assert_eq!(covered.get(&("input.csv", 3)), Some(&&records[1].tx));
+    assert_eq!(covered.len(), 2);
+    // Records that are part of the loaded state do not count.
+    assert_eq!(Wal::covered(&records, 1).keys().collect::<Vec<_>>(), [&("input.csv", 3)]);
+    assert!(Wal::covered(&records, 2).is_empty());
+
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn sequence() {
+    let path = path("sequence");
+    fill(&path);
+    let (mut wal, mut records) = Wal::open(&path, &WalConfig::new()).unwrap();
+
+    // A duplicate sequence number is not replayed as if it were the next one.
+    let mut duplicate = records[1].clone();
+    duplicate.tx = Tx::new_deposit(3, 1, Decimal::from(1));
+    records.push(duplicate);
+    assert!(matches!(Wal::replay(&records, &mut Db::new()), Err(WalErr::Sequence(2, 3))));
+
+    // Neither is a log that does not follow the state it is replayed into.
+    records.truncate(2);
+    let mut db = Db::new();
+    db.process(&Tx::new_deposit(1, 1, Decimal::from(5))).unwrap();
+    assert_eq!(Wal::replay(&records, &mut db).unwrap(), 1);
+    assert!(matches!(
+      Wal::replay(&records[1..], &mut Db::new()),
+      Err(WalErr::Sequence(2, 1))
+    ));
+
+    wal.truncate().unwrap();
+    let (_, records) = Wal::open(&path, &WalConfig::new()).unwrap();
+    assert!(records.is_empty());
+
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn config() {
+    let path = path("config");
+    let config = WalConfig::new().with("dispute_policy", "negative");
+    let (mut wal, _) = Wal::open(&path, &config).unwrap();
+    let tx = Tx::new_deposit(1, 1, Decimal::from(5));
+    wal.append(&WalRecord { seq: 1, input: String::new(), line: 2, tx }).unwrap();
+
+    // The records cannot be replayed under another configuration.
+    let err = Wal::open(&path, &WalConfig::new().with("dispute_policy", "reject"));
+    assert!(matches!(err, Err(WalErr::Config(name, logged, now))
+      if name == "dispute_policy" && logged == "negative" && now == "reject"));
+    assert!(matches!(Wal::open(&path, &WalConfig::new()), Err(WalErr::Config(..))));
+
+    // Once the records are covered by a snapshot, the log takes on a new configuration.
+    let (mut wal, records) = Wal::open(&path, &config).unwrap();
+    assert_eq!(records.len(), 1);
+    wal.truncate().unwrap();
+    let (mut wal, _) = Wal::open(&path, &WalConfig::new()).unwrap();
+    wal.append(&records[0]).unwrap();
+    assert!(Wal::open(&path, &config).is_err());
+    assert!(Wal::open(&path, &WalConfig::new()).is_ok());
+
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn torn_write() {
+    let path = path("torn");
+    fill(&path);
+    let len = fs::metadata(&path).unwrap().len();
+
+    let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+    file.write_all(b"0badf00d {\"seq\":3,").unwrap();
+
+    let (_, records) = Wal::open(&path, &WalConfig::new()).unwrap();
+    assert_eq!(records.len(), 2);
+    assert_eq!(fs::metadata(&path).unwrap().len(), len);
+
+    fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn corrupt() {
+    let path = path("corrupt");
+    fill(&path);
+
+    let contents = fs::read_to_string(&path).unwrap().replacen("\"5\"", "\"6\"", 1);
+    fs::write(&path, contents).unwrap();
+
+    assert!(matches!(Wal::open(&path, &WalConfig::new()), Err(WalErr::Corrupt(2))));
+
+    fs::remove_file(path).unwrap();
+  }
+}
@@ -13,9 +13,30 @@
 
 #![warn(clippy::all)]
 
-use crate::{ClientId, TxErr, TxId};
+use crate::{ClientId, TxErrKind, TxId};
 use derive_more::Display;
 use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+/// A held withdraw keeps track of how much of it is disputed.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct WithdrawHeld {
+  disputed: Decimal,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct WithdrawReleased;
+
+/// A reversed withdraw keeps track of how much of it was charged back.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
+pub struct WithdrawReversed {
+  reversed: Decimal,
+}
+
+pub trait WithdrawState {}
+impl WithdrawState for WithdrawHeld {}
+impl WithdrawState for WithdrawReleased {}
+impl WithdrawState for WithdrawReversed {}
 
 /// A withdrawal is a debit to the client's account.
 ///
@@ -31,23 +52,22 @@ use rust_decimal::Decimal;
 ///
 /// * An error is thrown if the amount being withdrawn is more than the available balance
 ///   in the client's account.
-#[derive(Debug, Display, PartialEq, Eq, Clone, Copy)]
+///
+/// # Notes
+///
+/// * A withdrawal can be disputed, in which case its amount is held until the dispute is
+///   either resolved (the withdrawal stands) or charged back (the client is credited
+///   back).
+#[derive(Debug, Display, PartialEq, Eq, Hash, Clone, Copy, Serialize, Deserialize)]
 #[display(fmt = "Withdrawal {} {} Amount={}", id, client, amount)]
-pub struct Withdraw {
+pub struct Withdraw<State: WithdrawState = WithdrawReleased> {
   id: TxId,
   client: ClientId,
   amount: Decimal,
+  state: State,
 }
 
-impl Withdraw {
-  pub fn new(id: TxId, client: ClientId, amount: Decimal) -> Result<Self, TxErr> {
-    if amount.is_sign_negative() {
-      Err(TxErr::NegativeAmount)
-    } else {
-      Ok(Self { id, client, amount })
-    }
-  }
-
+impl<State: WithdrawState> Withdraw<State> {
   /// Get the withdraw's id.
   pub fn id(&self) -> TxId {
     self.id
@@ -64,9 +84,78 @@ impl Withdraw {
   }
 }
 
+impl Withdraw<WithdrawReleased> {
+  pub fn new(id: TxId, client: ClientId, amount: Decimal) -> Result<Self, TxErrKind> {
+    if amount.is_sign_negative() {
+      Err(TxErrKind::NegativeAmount)
+    } else {
+      Ok(Self { id, client, amount, state: WithdrawReleased })
+    }
+  }
+
+  /// Hold *disputed* out of the withdraw's amount.
+  ///
+  /// The caller must ensure that *disputed* is not larger than the withdraw's amount.
+  pub fn hold(self, disputed: Decimal) -> Withdraw<WithdrawHeld> {
+    assert!(disputed <= self.amount);
+
+    Withdraw::<WithdrawHeld> {
+      id: self.id,
+      client: self.client,
+      amount: self.amount,
+      state: WithdrawHeld { disputed },
+    }
+  }
+}
+
+impl Withdraw<WithdrawHeld> {
+  /// Get the disputed part of the withdraw's amount.
+  pub fn disputed(&self) -> Decimal {
+    self.state.disputed
+  }
+
+  /// Get the part of the withdraw's amount that is not disputed.
+  pub fn undisputed(&self) -> Decimal {
+    self.amount - self.state.disputed
+  }
+
+  /// Hold an additional *disputed* amount out of the withdraw's amount.
+  ///
+  /// The caller must ensure that *disputed* is not larger than the undisputed amount.
+  pub fn hold_more(&mut self, disputed: Decimal) {
+    assert!(disputed <= self.undisputed());
+    self.state.disputed += disputed;
+  }
+
+  pub fn release(self) -> Withdraw<WithdrawReleased> {
+    Withdraw::<WithdrawReleased> {
+      id: self.id,
+      client: self.client,
+      amount: self.amount,
+      state: WithdrawReleased,
+    }
+  }
+
+  pub fn reverse(self) -> Withdraw<WithdrawReversed> {
+    Withdraw::<WithdrawReversed> {
+      id: self.id,
+      client: self.client,
+      amount: self.amount,
+      state: WithdrawReversed { reversed: self.state.disputed },
+    }
+  }
+}
+
+impl Withdraw<WithdrawReversed> {
+  /// Get the part of the withdraw's amount that was charged back.
+  pub fn reversed(&self) -> Decimal {
+    self.state.reversed
+  }
+}
+
 #[cfg(test)]
 mod withdraw_tests {
-  use crate::{ClientId, TxErr, TxId, Withdraw};
+  use crate::{withdraw::WithdrawReleased, ClientId, TxErrKind, TxId, Withdraw};
   use rust_decimal::Decimal;
 
   #[test]
@@ -77,7 +166,7 @@ mod withdraw_tests {
 
     assert_eq!(
       Withdraw::new(tx_id, client_id, amount),
-      Ok(Withdraw { id: tx_id, client: client_id, amount })
+      Ok(Withdraw { id: tx_id, client: client_id, amount, state: WithdrawReleased })
     );
   }
 
@@ -85,7 +174,21 @@ mod withdraw_tests {
   fn negative_amount() {
     assert_eq!(
       Withdraw::new(TxId::new(1), ClientId::new(1), Decimal::from(-5)),
-      Err(TxErr::NegativeAmount)
+      Err(TxErrKind::NegativeAmount)
     );
   }
+
+  #[test]
+  fn partial_holds() {
+    let tx = Withdraw::new(TxId::new(1), ClientId::new(1), Decimal::from(5)).unwrap();
+
+    let mut tx = tx.hold(Decimal::from(2));
+    assert_eq!(tx.disputed(), Decimal::from(2));
+    assert_eq!(tx.undisputed(), Decimal::from(3));
+
+    tx.hold_more(Decimal::from(3));
+    assert_eq!(tx.disputed(), Decimal::from(5));
+    assert_eq!(tx.undisputed(), Decimal::from(0));
+    assert_eq!(tx.reverse().reversed(), Decimal::from(5));
+  }
 }
@@ -0,0 +1,550 @@
+// This file is part of transactions-engine.
+//
+// transactions-engine is free software: you can redistribute it and/or modify it under
+// the terms of the GNU General Public License as published by the Free Software
+// Foundation, either version 3 of the License, or (at your option) any later version.
+//
+// transactions-engine is distributed in the hope that it will be useful, but WITHOUT ANY
+// WARRANTY; without even the implied warranty of MERCHANTABILITY or FITNESS FOR A
+// PARTICULAR PURPOSE.  See the GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License along with
+// transactions-engine.  If not, see <https://www.gnu.org/licenses/>.
+
+#![warn(clippy::all)]
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// A fresh scratch directory for test *name*.
+fn dir(name: &str) -> PathBuf {
+  let dir =
+    std::env::temp_dir().join(format!("tx-engine-cli-{}-{}", std::process::id(), name));
+  let _ = fs::remove_dir_all(&dir);
+  fs::create_dir_all(&dir).unwrap();
+  dir
+}
+
+/// The number of records in the write-ahead log at *path*, after its configuration line.
+fn wal_records(path: impl AsRef<Path>) -> usize {
+  fs::read_to_string(path).unwrap().lines().count() - 1
+}
+
+/// Run the executable with *args*, returning its standard output.
+fn run(args: &[&str]) -> String {
+  let output = Command::new(env!("CARGO_BIN_EXE_tx_engine")).args(args).output().unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  String::from_utf8(output.stdout).unwrap()
+}
+
+/// Run the executable with *args*, expecting it to fail, returning its exit code,
+/// standard output and standard error.
+fn run_failing(args: &[&str]) -> (i32, String, String) {
+  let output = Command::new(env!("CARGO_BIN_EXE_tx_engine")).args(args).output().unwrap();
+  assert!(!output.status.success());
+
+  let stdout = String::from_utf8(output.stdout).unwrap();
+  let stderr = String::from_utf8(output.stderr).unwrap();
+  (output.status.code().unwrap(), stdout, stderr)
+}
+
+/// Run the executable with *args* and *stdin* as its standard input, returning its
+/// standard output.
+fn run_with_stdin(args: &[&str], stdin: &str) -> String {
+  let mut child = Command::new(env!("CARGO_BIN_EXE_tx_engine"))
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .unwrap();
+  child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+
+  let output = child.wait_with_output().unwrap();
+  assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+  String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn stdin() {
+  let dir = dir("stdin");
+  let input = dir.join("in.csv");
+  fs::write(
+    &input,
+    "type,client,tx,amount
+withdrawal,1,2,4
+",
+  )
+  .unwrap();
+
+  // The standard input is processed before the file, so the withdrawal succeeds.
+  let output = run_with_stdin(
+    &["process", "-", input.to_str().unwrap()],
+    "type,client,tx,amount
+deposit,1,1,10
+",
+  );
+  assert!(output.contains("1,6.0000,0.0000,"));
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn output_columns() {
+  let dir = dir("columns");
+  let input = dir.join("in.csv");
+  fs::write(&input, "type,client,tx,amount\ndeposit,1,1,10\n").unwrap();
+
+  // The original columns keep their positions, and the newer ones follow them. The debt,
+  // credit used and dispute policy are only written when their feature is in use.
+  let output = run(&["process", input.to_str().unwrap()]);
+  let header = output.lines().next().unwrap();
+  assert_eq!(header, "client,available,held,total,locked,authorized");
+
+  let limits = dir.join("limits.csv");
+  fs::write(
+    &limits,
+    "client,limit
+2,5
+",
+  )
+  .unwrap();
+  let output = run(&[
+    "process",
+    "--dispute-policy",
+    "debt",
+    "--credit-limits",
+    limits.to_str().unwrap(),
+    input.to_str().unwrap(),
+  ]);
+  let lines: Vec<_> = output.lines().collect();
+  assert_eq!(
+    lines[0],
+    "client,available,held,total,locked,authorized,debt,credit_used,dispute_policy"
+  );
+  assert_eq!(lines[1], "1,10.0000,0.0000,10.0000,false,0.0000,0.0000,0.0000,debt");
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn output_scale() {
+  let dir = dir("scale");
+  let input = dir.join("in.csv");
+  fs::write(&input, "type,client,tx,amount\ndeposit,1,1,9.25\ndeposit,2,2,1\n").unwrap();
+
+  let output = run(&["process", "--scale", "27", input.to_str().unwrap()]);
+  let row = output.lines().nth(1).unwrap();
+  assert_eq!(row.split(',').nth(1), Some("9.250000000000000000000000000"));
+
+  // An amount that cannot get every decimal place is an error rather than a shorter
+  // column, and no account is written.
+  let (_, stdout, stderr) =
+    run_failing(&["process", "--scale", "28", input.to_str().unwrap()]);
+  assert!(stdout.is_empty());
+  assert!(stderr.contains("9.25 does not fit in 28 decimal places"), "{}", stderr);
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn statements() {
+  let dir = dir("statements");
+  let input = dir.join("in.csv");
+  fs::write(
+    &input,
+    "type,client,tx,amount\ndeposit,1,1,10\nauthorize,1,2,4\ncapture,1,2,3\n",
+  )
+  .unwrap();
+
+  run(&["process", "--statements", dir.to_str().unwrap(), input.to_str().unwrap()]);
+
+  // Every entry adds up to the total, including the authorized funds.
+  let statement = fs::read_to_string(dir.join("client-1.csv")).unwrap();
+  let lines: Vec<_> = statement.lines().collect();
+  assert_eq!(
+    lines,
+    [
+      "tx,type,kind,available_delta,held_delta,available,held,authorized_delta,authorized",
+      "1,deposit,transaction,10,0,10,0,0,0",
+      "2,authorize,transaction,-4,0,6,0,4,4",
+      "2,capture,transaction,1,0,7,0,-4,0",
+    ]
+  );
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn verification() {
+  let dir = dir("verification");
+  let (input, chain_log) = (dir.join("in.csv"), dir.join("chain.csv"));
+  let (input, chain_log) = (input.to_str().unwrap(), chain_log.to_str().unwrap());
+  fs::write(input, "type,client,tx,amount\ndeposit,1,1,10\nwithdrawal,1,2,4\n").unwrap();
+
+  run(&["process", "--chain-log", chain_log, input]);
+  let log = fs::read_to_string(chain_log).unwrap();
+  let head = log.lines().last().unwrap().rsplit(',').next().unwrap().to_owned();
+
+  let path = |name| dir.join(name).to_str().unwrap().to_owned();
+  let side_effects = [
+    ("--wal", path("w.log")),
+    ("--rejects", path("rejects.csv")),
+    ("--chain-log", path("other.csv")),
+    ("--save-snapshot", path("s.json")),
+    ("--statements", path("")),
+  ];
+
+  for mode in [["--verify-chain", chain_log], ["--verify-head", &head]] {
+    run(&["process", mode[0], mode[1], input]);
+
+    // Verification only recomputes the chain from the input, so it cannot write (or
+    // replay) any other files.
+    for (option, value) in &side_effects {
+      let (code, _, stderr) =
+        run_failing(&["process", mode[0], mode[1], option, value, input]);
+      assert_eq!(code, 2);
+      assert!(stderr.contains("cannot be used with"), "{}", stderr);
+    }
+  }
+
+  assert!(!dir.join("w.log").exists());
+
+  // A changed input no longer verifies in either mode.
+  fs::write(input, "type,client,tx,amount\ndeposit,1,1,99999\nwithdrawal,1,2,4\n")
+    .unwrap();
+  for mode in [["--verify-chain", chain_log], ["--verify-head", &head]] {
+    let (_, _, stderr) = run_failing(&["process", mode[0], mode[1], input]);
+    assert!(stderr.contains("diverge"), "{}", stderr);
+  }
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn wal_resume() {
+  let dir = dir("wal-resume");
+  let input = dir.join("in.csv");
+  let wal = dir.join("w.log");
+  fs::write(&input, "type,client,tx,amount\ndeposit,1,1,10\ndispute,1,1,2\n").unwrap();
+
+  let args = ["process", "--wal", wal.to_str().unwrap(), input.to_str().unwrap()];
+  let first = run(&args);
+  assert!(first.contains("1,8.0000,2.0000,"));
+
+  // The rows already in the log are not applied a second time.
+  let second = run(&args);
+  assert_eq!(first, second);
+  assert_eq!(wal_records(&wal), 2);
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn wal_new_input() {
+  let dir = dir("wal-new-input");
+  let (input, wal) = (dir.join("in.csv"), dir.join("w.log"));
+  let args = ["process", "--wal", wal.to_str().unwrap(), input.to_str().unwrap()];
+
+  fs::write(&input, "type,client,tx,amount\ndeposit,1,1,10\ndeposit,1,2,5\n").unwrap();
+  assert!(run(&args).contains("1,15.0000,"));
+
+  // A new input under the same name does not match the logged rows, so none of it is
+  // skipped.
+  fs::write(
+    &input,
+    "type,client,tx,amount\ndeposit,1,3,100\ndeposit,1,4,1\ndeposit,1,5,1000\n",
+  )
+  .unwrap();
+  let output = run(&args);
+  assert!(output.contains("1,1116.0000,"), "{}", output);
+  assert_eq!(wal_records(&wal), 5);
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn wal_config() {
+  let dir = dir("wal-config");
+  let (input, wal) = (dir.join("in.csv"), dir.join("w.log"));
+  let (input, wal) = (input.to_str().unwrap(), wal.to_str().unwrap());
+  fs::write(
+    input,
+    "type,client,tx,amount\ndeposit,1,1,10\nwithdrawal,1,2,8\ndispute,1,1,\n",
+  )
+  .unwrap();
+
+  let output = run(&["process", "--wal", wal, "--dispute-policy", "negative", input]);
+  assert!(output.contains("1,-8.0000,10.0000,"), "{}", output);
+
+  // The log cannot be replayed under another configuration.
+  let (code, stdout, stderr) = run_failing(&["process", "--wal", wal, input]);
+  assert_eq!(code, 1);
+  assert!(stdout.is_empty());
+  assert!(stderr.contains("dispute_policy=negative, but it is now reject"), "{}", stderr);
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn wal_after_snapshot() {
+  let dir = dir("wal-snapshot");
+  let (input, wal, snapshot) =
+    (dir.join("today.csv"), dir.join("w.log"), dir.join("s.json"));
+  let (input, wal, snapshot) =
+    (input.to_str().unwrap(), wal.to_str().unwrap(), snapshot.to_str().unwrap());
+
+  fs::write(input, "type,client,tx,amount\ndeposit,1,1,10\ndeposit,1,2,5\n").unwrap();
+  let output = run(&["process", "--wal", wal, "--save-snapshot", snapshot, input]);
+  assert!(output.contains("1,15.0000,"));
+
+  // The snapshot covers the whole log, which is truncated, so a new input with the same
+  // name is processed in full.
+  assert_eq!(wal_records(wal), 0);
+  fs::write(
+    input,
+    "type,client,tx,amount\ndeposit,1,3,100\ndeposit,1,4,1\ndeposit,1,5,1000\n",
+  )
+  .unwrap();
+  let output = run(&["process", "--wal", wal, "--load-snapshot", snapshot, input]);
+  assert!(output.contains("1,1116.0000,"), "{}", output);
+  assert_eq!(wal_records(wal), 3);
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn opening_balances() {
+  let dir = dir("opening");
+  let (opening, input) = (dir.join("opening.csv"), dir.join("in.csv"));
+  fs::write(&input, "type,client,tx,amount\ndeposit,1,1,1\n").unwrap();
+
+  // Both the baseline output format and the current one, which has more columns, can be
+  // read back.
+  fs::write(&opening, "client,available,held,total,locked\n1,7,2,9,false\n").unwrap();
+  let output = run(&[
+    "process",
+    "--opening-balances",
+    opening.to_str().unwrap(),
+    input.to_str().unwrap(),
+  ]);
+  assert!(output.contains("1,8.0000,2.0000,10.0000,false,0.0000\n"));
+
+  fs::write(&opening, &output).unwrap();
+  let output = run(&[
+    "process",
+    "--opening-balances",
+    opening.to_str().unwrap(),
+    input.to_str().unwrap(),
+  ]);
+  assert!(output.contains("1,9.0000,2.0000,11.0000,false,0.0000\n"));
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn expiry_replay() {
+  let dir = dir("expiry");
+  let (input, wal) = (dir.join("in.csv"), dir.join("wal"));
+  fs::write(
+    &input,
+    "type,client,tx,amount,timestamp\n\
+     deposit,1,1,10,\n\
+     authorize,1,2,4,100\n\
+     withdrawal,1,3,1,200\n\
+     capture,1,2,,\n",
+  )
+  .unwrap();
+
+  // The withdrawal releases the expired authorization, so the capture fails.
+  let args = [
+    "process",
+    "--authorization-ttl",
+    "60",
+    "--wal",
+    wal.to_str().unwrap(),
+    input.to_str().unwrap(),
+  ];
+  let output = run(&args);
+  assert!(output.contains("1,9.0000,0.0000,9.0000,false,0.0000\n"));
+
+  // Replaying the log alone releases it again.
+  assert_eq!(run(&args), output);
+  assert_eq!(wal_records(&wal), 3);
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn json_formats() {
+  let dir = dir("json");
+  let input = dir.join("in.jsonl");
+  fs::write(
+    &input,
+    "{\"type\":\"deposit\",\"client\":1,\"tx\":1,\"amount\":\"2.5\"}\n\
+     \n\
+     {\"type\":\"deposit\",\"client\":2,\"tx\":2,\"amount\":1.1234}\n\
+     {\"type\":\"withdrawal\",\"client\":1,\"tx\":3,\"amount\":0.1}\n",
+  )
+  .unwrap();
+  let input = input.to_str().unwrap();
+
+  // Amounts may be strings or numbers, and are written as strings.
+  let args = ["process", "--input-format", "jsonl", "--output-format", "json", input];
+  let output: serde_json::Value = serde_json::from_str(&run(&args)).unwrap();
+  let accounts = output.as_array().unwrap();
+  assert_eq!(accounts.len(), 2);
+  assert_eq!(accounts[0]["client"], 1);
+  assert_eq!(accounts[0]["available"], "2.4000");
+  assert_eq!(accounts[0]["locked"], false);
+  assert_eq!(accounts[1]["client"], 2);
+  assert_eq!(accounts[1]["total"], "1.1234");
+
+  let args = ["process", "--input-format", "jsonl", "--output-format", "jsonl", input];
+  let output = run(&args);
+  let lines: Vec<serde_json::Value> =
+    output.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+  assert_eq!(lines, *accounts);
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn rejects_report() {
+  let dir = dir("rejects");
+  let (input, limits, rejects) =
+    (dir.join("in.csv"), dir.join("velocity.csv"), dir.join("rejects.csv"));
+  fs::write(
+    &input,
+    "type,client,tx,amount,timestamp\n\
+     deposit,1,1,200,\n\
+     withdrawal,1,2,500,10\n\
+     withdrawal,1,3,60,20\n\
+     withdrawal,1,4,50,30\n\
+     deposit,1,5,abc,\n",
+  )
+  .unwrap();
+  fs::write(&limits, "client,daily_amount,hourly_count\n,100,\n").unwrap();
+
+  let (input, rejects) = (input.to_str().unwrap(), rejects.to_str().unwrap());
+  let output = run(&[
+    "process",
+    "--velocity-limits",
+    limits.to_str().unwrap(),
+    "--rejects",
+    rejects,
+    input,
+  ]);
+  assert!(output.contains("1,140.0000,0.0000,"));
+
+  // The original fields come between the position of the row and the reason.
+  let mut reader = csv::Reader::from_path(rejects).unwrap();
+  let header: Vec<_> = reader.headers().unwrap().iter().collect();
+  assert_eq!(
+    header.join(","),
+    "file,line,type,client,tx,amount,timestamp,code,message,velocity"
+  );
+
+  let rows: Vec<Vec<String>> =
+    reader.records().map(|row| row.unwrap().iter().map(String::from).collect()).collect();
+  assert_eq!(rows.len(), 3);
+
+  let reason = |row: &[String]| row[7..].join(",");
+  assert_eq!(rows[0][..7], [input, "3", "withdrawal", "1", "2", "500", "10"]);
+  assert_eq!(reason(&rows[0]), "insufficient_funds,Insufficient funds,false");
+  assert_eq!(rows[1][..7], [input, "5", "withdrawal", "1", "4", "50", "30"]);
+  assert_eq!(
+    reason(&rows[1]),
+    "velocity_exceeded,Withdrawal would exceed the velocity limits,true"
+  );
+  // Malformed rows keep their original fields too.
+  assert_eq!(
+    rows[2][..8],
+    [input, "6", "deposit", "1", "5", "abc", "", "invalid_record"]
+  );
+  assert!(rows[2][8].contains("abc"));
+  assert_eq!(rows[2][9], "false");
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn error_limits() {
+  let dir = dir("error-limits");
+  let input = dir.join("in.csv");
+  fs::write(
+    &input,
+    "type,client,tx,amount\n\
+     deposit,1,1,10\n\
+     withdrawal,1,2,50\n\
+     deposit,1,3,x\n\
+     withdrawal,1,4,60\n\
+     deposit,1,5,1\n",
+  )
+  .unwrap();
+  let input = input.to_str().unwrap();
+
+  // The first rejected row aborts a strict run, without any balances.
+  let (code, stdout, stderr) = run_failing(&["process", "--strict", input]);
+  assert_eq!(code, 1);
+  assert_eq!(stdout, "");
+  assert!(stderr.contains(&format!("{}:3: Insufficient funds", input)), "{}", stderr);
+  assert!(stderr.contains("withdrawal,1,2,50"));
+
+  // Up to the maximum number of rejected rows are tolerated.
+  let (code, stdout, stderr) = run_failing(&["process", "--max-errors", "2", input]);
+  assert_eq!(code, 1);
+  assert_eq!(stdout, "");
+  assert!(stderr.contains("More than 2 rows rejected"), "{}", stderr);
+  assert!(stderr.contains(&format!("{}:5: Insufficient funds", input)));
+  assert!(stderr.contains("withdrawal,1,4,60"));
+
+  let output = run(&["process", "--max-errors", "3", input]);
+  assert!(output.contains("1,11.0000,0.0000,"));
+
+  fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn validate() {
+  let dir = dir("validate");
+  let input = dir.join("in.csv");
+  fs::write(
+    &input,
+    "type,client,tx,amount\n\
+     deposit,1,1,10\n\
+     withdrawal,1,2,50\n\
+     deposit,1,3,x\n\
+     withdrawal,1,4,60\n\
+     deposit,1,5,1\n",
+  )
+  .unwrap();
+  let input = input.to_str().unwrap();
+
+  // Every problem is reported with its line, then counted by category.
+  let (code, stdout, stderr) = run_failing(&["validate", input]);
+  assert_eq!(code, 1);
+  let lines: Vec<_> = stdout.lines().collect();
+  assert_eq!(lines.len(), 6, "{}", stdout);
+  assert_eq!(
+    lines[0],
+    format!("{}:3: semantic insufficient_funds: Insufficient funds", input)
+  );
+  assert!(lines[1].starts_with(&format!("{}:4: schema invalid_record: ", input)));
+  assert_eq!(
+    lines[2],
+    format!("{}:5: semantic insufficient_funds: Insufficient funds", input)
+  );
+  assert_eq!(lines[3], "5 rows checked, 3 problems found");
+  assert_eq!(lines[4], "  schema invalid_record: 1");
+  assert_eq!(lines[5], "  semantic insufficient_funds: 2");
+  assert!(stderr.contains("3 problems found"));
+
+  // A clean input passes, still without any balances.
+  fs::write(input, "type,client,tx,amount\ndeposit,1,1,10\n").unwrap();
+  assert_eq!(run(&["validate", input]), "1 rows checked, 0 problems found\n");
+
+  fs::remove_dir_all(dir).unwrap();
+}
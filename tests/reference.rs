@@ -75,10 +75,6 @@ fn reference() {
             csv_writer.serialize(account).unwrap();
           }
 
-          for account in db.accounts_locked() {
-            csv_writer.serialize(account).unwrap();
-          }
-
           csv_writer.flush().unwrap();
         }
 